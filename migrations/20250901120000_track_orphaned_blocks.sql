@@ -0,0 +1,26 @@
+-- Блоки и coinbase вытесненной при reorg ветки не удаляем, а помечаем
+ALTER TABLE blocks
+ADD COLUMN is_orphaned BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE transactions
+ADD COLUMN is_orphaned BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- На одной высоте может быть несколько блоков, но активный только один
+ALTER TABLE blocks DROP CONSTRAINT blocks_height_key;
+CREATE UNIQUE INDEX idx_blocks_active_height ON blocks(height) WHERE NOT is_orphaned;
+
+CREATE TABLE stale_blocks (
+    id SERIAL PRIMARY KEY,
+    hash VARCHAR(64) UNIQUE NOT NULL,
+    height BIGINT NOT NULL,
+    guessed_miner VARCHAR(255),
+    replaced_by_hash VARCHAR(64),
+    replaced_by_miner VARCHAR(255),
+    fork_height BIGINT NOT NULL,
+    fork_hash VARCHAR(64) NOT NULL,
+    reorg_depth INTEGER NOT NULL,
+    detected_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_stale_blocks_height ON stale_blocks(height);
+CREATE INDEX idx_stale_blocks_detected_at ON stale_blocks(detected_at);
\ No newline at end of file
@@ -0,0 +1,11 @@
+ALTER TABLE transactions
+ADD COLUMN pool_name VARCHAR(255),
+ADD COLUMN pool_slug VARCHAR(255),
+ADD COLUMN pool_match_method VARCHAR(32);
+
+ALTER TABLE stale_blocks
+ADD COLUMN pool_slug VARCHAR(255),
+ADD COLUMN replaced_by_pool_slug VARCHAR(255);
+
+-- Индексы
+CREATE INDEX idx_transactions_pool_slug ON transactions(pool_slug);
\ No newline at end of file
@@ -0,0 +1,15 @@
+CREATE TABLE coinbase_outputs (
+    id SERIAL PRIMARY KEY,
+    block_hash VARCHAR(64) NOT NULL,
+    vout_index INTEGER NOT NULL,
+    value BIGINT NOT NULL,
+    script_type VARCHAR(32) NOT NULL,
+    address VARCHAR(255),
+    scriptpubkey_hex TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (block_hash, vout_index),
+    FOREIGN KEY (block_hash) REFERENCES blocks(hash) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_coinbase_outputs_block_hash ON coinbase_outputs(block_hash);
+CREATE INDEX idx_coinbase_outputs_address ON coinbase_outputs(address);
\ No newline at end of file
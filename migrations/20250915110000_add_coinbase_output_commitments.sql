@@ -0,0 +1,5 @@
+ALTER TABLE coinbase_outputs ADD COLUMN commitment_kind VARCHAR(32);
+ALTER TABLE coinbase_outputs ADD COLUMN commitment_payload_hex TEXT;
+
+-- Индексы
+CREATE INDEX idx_coinbase_outputs_commitment_kind ON coinbase_outputs(commitment_kind) WHERE commitment_kind IS NOT NULL;
\ No newline at end of file
@@ -0,0 +1,53 @@
+-- Маркеры merge mining в блоке (одна строка на маркер). Сети с тегом в OP_RETURN различаются,
+-- а все Namecoin-style aux-pow сети видны в coinbase биткоина одним маркером aux_pow.
+-- Для него сохраняем размер aux merkle-дерева — верхнюю границу числа сетей в блоке.
+CREATE TABLE block_merged_mining (
+    id SERIAL PRIMARY KEY,
+    block_hash VARCHAR(64) NOT NULL,
+    marker VARCHAR(32) NOT NULL,
+    aux_merkle_size BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (block_hash, marker),
+    FOREIGN KEY (block_hash) REFERENCES blocks(hash) ON DELETE CASCADE
+);
+
+-- Индексы
+CREATE INDEX idx_block_merged_mining_block_hash ON block_merged_mining(block_hash);
+CREATE INDEX idx_block_merged_mining_marker ON block_merged_mining(marker);
+
+-- Доля блоков каждого пула с маркером merge mining за окно [p_from, p_to)
+CREATE FUNCTION merged_mining_by_pool(p_from TIMESTAMPTZ, p_to TIMESTAMPTZ)
+RETURNS TABLE (
+    pool_slug VARCHAR,
+    marker VARCHAR,
+    merged_blocks BIGINT,
+    pool_blocks BIGINT,
+    share NUMERIC,
+    max_aux_merkle_size BIGINT
+)
+AS $$
+    WITH window_blocks AS (
+        SELECT t.pool_slug, b.hash
+        FROM blocks b
+        JOIN transactions t ON t.block_hash = b.hash AND t.is_coinbase
+        WHERE NOT b.is_orphaned
+          AND b."timestamp" >= p_from
+          AND b."timestamp" < p_to
+    ),
+    pool_totals AS (
+        SELECT wb.pool_slug, COUNT(*) AS total
+        FROM window_blocks wb
+        GROUP BY wb.pool_slug
+    )
+    SELECT wb.pool_slug,
+           mm.marker,
+           COUNT(*) AS merged_blocks,
+           pt.total AS pool_blocks,
+           ROUND(COUNT(*)::NUMERIC / pt.total, 4) AS share,
+           MAX(mm.aux_merkle_size) AS max_aux_merkle_size
+    FROM window_blocks wb
+    JOIN block_merged_mining mm ON mm.block_hash = wb.hash
+    JOIN pool_totals pt ON pt.pool_slug IS NOT DISTINCT FROM wb.pool_slug
+    GROUP BY wb.pool_slug, mm.marker, pt.total
+    ORDER BY wb.pool_slug, mm.marker;
+$$ LANGUAGE SQL STABLE;
\ No newline at end of file
@@ -0,0 +1,20 @@
+-- Статистика комиссий по полному списку транзакций блока (при full_block_analysis)
+CREATE TABLE block_fee_stats (
+    id SERIAL PRIMARY KEY,
+    block_hash VARCHAR(64) UNIQUE NOT NULL,
+    total_fees BIGINT NOT NULL,
+    total_vsize BIGINT NOT NULL,
+    transactions_count INTEGER NOT NULL,
+    min_feerate DOUBLE PRECISION NOT NULL,
+    max_feerate DOUBLE PRECISION NOT NULL,
+    median_feerate DOUBLE PRECISION NOT NULL,
+    p10_feerate DOUBLE PRECISION NOT NULL,
+    p25_feerate DOUBLE PRECISION NOT NULL,
+    p75_feerate DOUBLE PRECISION NOT NULL,
+    p90_feerate DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (block_hash) REFERENCES blocks(hash) ON DELETE CASCADE
+);
+
+-- Индексы
+CREATE INDEX idx_block_fee_stats_block_hash ON block_fee_stats(block_hash);
\ No newline at end of file
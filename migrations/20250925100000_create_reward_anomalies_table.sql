@@ -0,0 +1,17 @@
+-- Блоки, в которых coinbase не сходится с субсидией и комиссиями
+CREATE TABLE reward_anomalies (
+    id SERIAL PRIMARY KEY,
+    block_hash VARCHAR(64) UNIQUE NOT NULL,
+    height BIGINT NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    expected_subsidy BIGINT NOT NULL,
+    fees BIGINT,
+    coinbase_total BIGINT NOT NULL,
+    difference BIGINT NOT NULL,
+    detected_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (block_hash) REFERENCES blocks(hash) ON DELETE CASCADE
+);
+
+-- Индексы
+CREATE INDEX idx_reward_anomalies_height ON reward_anomalies(height);
+CREATE INDEX idx_reward_anomalies_kind ON reward_anomalies(kind);
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- До BIP30 одна и та же coinbase могла войти в два блока (91842/91812, 91880/91722),
+-- а при reorg один пул нередко публикует одинаковую coinbase в обеих ветках.
+-- Поэтому txid уникален только в пределах блока.
+ALTER TABLE transactions DROP CONSTRAINT transactions_txid_key;
+ALTER TABLE transactions ADD CONSTRAINT transactions_txid_block_hash_key UNIQUE (txid, block_hash);
\ No newline at end of file
@@ -0,0 +1 @@
+pub mod notifications;
\ No newline at end of file
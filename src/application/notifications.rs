@@ -0,0 +1,152 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::config::config::NotificationsConfig;
+use crate::domain::block::Block;
+use crate::domain::notification::Notification;
+use crate::domain::reward_anomaly::RewardAnomaly;
+use crate::domain::transaction::Transaction;
+use crate::infrastructure::queue::queue_service::ReorgEvent;
+use crate::utils::pool_identifier::PoolMatch;
+
+/// Превращает обработанные live-watcher'ом блоки и reorg'и в уведомления.
+/// Состояние (окно пулов, время прошлого блока) живёт только в памяти: после рестарта правила "разогреваются" заново.
+pub struct NotificationRules {
+    config: NotificationsConfig,
+    recent_pools: VecDeque<PoolMatch>,
+    dominant_pools: HashSet<String>,
+    last_block_timestamp: Option<u64>,
+}
+
+impl NotificationRules {
+    pub fn new(config: NotificationsConfig) -> Self {
+        Self {
+            config,
+            recent_pools: VecDeque::new(),
+            dominant_pools: HashSet::new(),
+            last_block_timestamp: None,
+        }
+    }
+
+    pub fn on_block(
+        &mut self,
+        block: &Block,
+        coinbase: &Transaction,
+        guessed_miner: &str,
+        pool: &PoolMatch,
+        reward_anomaly: Option<&RewardAnomaly>,
+    ) -> Vec<Notification> {
+        let height = block.get_height() as u64;
+        let block_hash = block.get_id();
+        let mut notifications = Vec::new();
+
+        if self.config.get_watched_pools().contains(&pool.slug) {
+            notifications.push(Notification::NewBlock {
+                height,
+                block_hash: block_hash.clone(),
+                timestamp: block.get_timestamp(),
+                pool_name: pool.name.clone(),
+                pool_slug: pool.slug.clone(),
+            });
+        }
+
+        if self.config.is_empty_blocks_enabled() && block.get_tx_count() <= 1 {
+            notifications.push(Notification::EmptyBlock {
+                height,
+                block_hash: block_hash.clone(),
+                pool_slug: pool.slug.clone(),
+            });
+        }
+
+        if self.config.is_unknown_miners_enabled() && pool.is_unknown() {
+            notifications.push(Notification::UnknownMiner {
+                height,
+                block_hash: block_hash.clone(),
+                guessed_miner: guessed_miner.to_string(),
+                miner_address: coinbase.get_main_reward_address().cloned().flatten(),
+            });
+        }
+
+        if let Some(last_timestamp) = self.last_block_timestamp {
+            // Время в заголовках не монотонно, поэтому saturating_sub.
+            let interval_secs = block.get_timestamp().saturating_sub(last_timestamp);
+            if interval_secs >= self.config.get_long_block_interval_secs() {
+                notifications.push(Notification::LongBlockInterval {
+                    height,
+                    block_hash: block_hash.clone(),
+                    interval_secs,
+                    threshold_secs: self.config.get_long_block_interval_secs(),
+                });
+            }
+        }
+        self.last_block_timestamp = Some(block.get_timestamp());
+
+        if self.config.is_reward_anomalies_enabled()
+            && let Some(anomaly) = reward_anomaly {
+            notifications.push(Notification::RewardAnomaly(anomaly.clone()));
+        }
+
+        notifications.extend(self.check_dominance(height, pool));
+
+        notifications
+    }
+
+    pub fn on_reorg(&mut self, reorg_event: &ReorgEvent) -> Vec<Notification> {
+        // Вытесненные блоки больше не считаются в окне доминирования; блоки новой ветки придут через on_block.
+        for _ in 0..reorg_event.depth() {
+            self.recent_pools.pop_back();
+        }
+        self.last_block_timestamp = None;
+
+        if reorg_event.depth() < self.config.get_min_reorg_depth() {
+            return Vec::new();
+        }
+
+        vec![Notification::Reorg {
+            fork_height: reorg_event.fork_height,
+            fork_hash: reorg_event.fork_hash.clone(),
+            depth: reorg_event.depth(),
+            orphaned_blocks: reorg_event.orphaned_blocks.clone(),
+            new_blocks: reorg_event.new_blocks.clone(),
+        }]
+    }
+
+    /// Сообщаем только о пересечении порога снизу вверх, а не о каждом блоке, пока пул выше порога.
+    /// Доля считается от полного размера окна, поэтому на первых блоках после старта ложных срабатываний нет.
+    fn check_dominance(&mut self, height: u64, pool: &PoolMatch) -> Option<Notification> {
+        let window_blocks = self.config.get_dominance_window_blocks().max(1);
+        let threshold = self.config.get_dominance_threshold();
+
+        self.recent_pools.push_back(pool.clone());
+        while self.recent_pools.len() > window_blocks {
+            self.recent_pools.pop_front();
+        }
+
+        let recent_pools = &self.recent_pools;
+        let pool_blocks_of = |slug: &str| recent_pools.iter().filter(|recent| recent.slug == slug).count();
+        let share_of = |pool_blocks: usize| pool_blocks as f64 / window_blocks as f64;
+
+        self.dominant_pools.retain(|slug| share_of(pool_blocks_of(slug)) >= threshold);
+
+        if pool.is_unknown() || self.dominant_pools.contains(&pool.slug) {
+            return None;
+        }
+
+        let pool_blocks = pool_blocks_of(&pool.slug);
+        let share = share_of(pool_blocks);
+        if share < threshold {
+            return None;
+        }
+
+        self.dominant_pools.insert(pool.slug.clone());
+
+        Some(Notification::PoolDominance {
+            height,
+            pool_name: pool.name.clone(),
+            pool_slug: pool.slug.clone(),
+            pool_blocks,
+            window_blocks,
+            share,
+            threshold,
+        })
+    }
+}
\ No newline at end of file
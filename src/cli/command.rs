@@ -0,0 +1,62 @@
+use anyhow::{anyhow, Result};
+use log::info;
+
+use crate::config::config::{Config, MessageBusKind};
+use crate::infrastructure::queue::dead_letter::DeadLetterQueue;
+use crate::infrastructure::queue::message_bus::build_message_bus;
+
+const DEFAULT_DLQ_INSPECT_LIMIT: usize = 100;
+
+const USAGE: &str = "usage: mining-mining-analytics_blocks <config> [dlq inspect [limit] | dlq replay]";
+
+/// Что запустить. Первый аргумент — путь к конфигу (его читает `Config::new`), за ним необязательная команда.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Без команды — сервис.
+    Run,
+    /// `dlq inspect [limit]`: напечатать первые `limit` сообщений `mining-analytics-dlq`.
+    DlqInspect { limit: usize },
+    /// `dlq replay`: отправить ещё не переотправленные сообщения DLQ обратно в исходный стрим.
+    DlqReplay,
+}
+
+impl Command {
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let command: Vec<&str> = args.iter().skip(2).map(String::as_str).collect();
+
+        match command.as_slice() {
+            [] => Ok(Command::Run),
+            ["dlq", "inspect"] => Ok(Command::DlqInspect { limit: DEFAULT_DLQ_INSPECT_LIMIT }),
+            ["dlq", "inspect", limit] => {
+                let limit = limit.parse().map_err(|e| anyhow!("invalid limit {}: {}", limit, e))?;
+                Ok(Command::DlqInspect { limit })
+            }
+            ["dlq", "replay"] => Ok(Command::DlqReplay),
+            _ => Err(anyhow!("unknown command '{}', {}", command.join(" "), USAGE)),
+        }
+    }
+
+    /// Команды DLQ работают с брокером напрямую и завершаются, не запуская сервис.
+    pub async fn run_dead_letter_command(&self, config: &Config) -> Result<()> {
+        // In-memory шина живёт внутри процесса сервиса: отдельной команде в ней читать нечего.
+        if config.get_message_bus() != MessageBusKind::RabbitMq {
+            return Err(anyhow!("DLQ commands need the rabbitmq message bus"));
+        }
+
+        let dead_letters = DeadLetterQueue::new(build_message_bus(config).await?, config.get_rabbitmq_config().get_streams());
+
+        match self {
+            Command::Run => {}
+            Command::DlqInspect { limit } => {
+                let printed = dead_letters.inspect(*limit).await?;
+                info!("{} dead letters printed", printed);
+            }
+            Command::DlqReplay => {
+                let replayed = dead_letters.replay().await?;
+                info!("{} dead letters replayed", replayed);
+            }
+        }
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -1,24 +1,364 @@
 use std::fs::File;
 use std::env;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::Error as _;
 use serde_json::from_reader;
 
+use crate::infrastructure::queue::codec::Codec;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     api_url: String,
+    #[serde(default)]
+    chain_source: ChainSourceKind,
+    #[serde(default)]
+    pipeline: PipelineTopology,
+    #[serde(default)]
+    message_bus: MessageBusKind,
+    #[serde(default)]
+    bitcoin_core_rpc: Option<BitcoinCoreRpcConfig>,
     interval_analytic_blocks: u64,
-    interval_read_rabbitmq_messages: u64,
+    #[serde(default)]
+    pools_file: Option<String>,
+    /// Загружать все транзакции блока ради статистики комиссий (дорого для mempool.space).
+    #[serde(default)]
+    full_block_analysis: bool,
+    #[serde(default = "default_max_reorg_depth")]
+    max_reorg_depth: u64,
+    #[serde(default = "default_state_dir")]
+    state_dir: String,
+    #[serde(default)]
+    backfill: Option<BackfillConfig>,
+    #[serde(default)]
+    notifications: NotificationsConfig,
+    #[serde(default)]
+    supervisor: SupervisorConfig,
+    /// Сколько ждать штатной остановки после SIGINT/SIGTERM.
+    #[serde(default = "default_shutdown_timeout_secs")]
+    shutdown_timeout_secs: u64,
     rabbitmq_config: RabbitMqConfig,
     database_config: DatabaseConfig
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainSourceKind {
+    #[default]
+    Mempool,
+    BitcoinCore,
+}
+
+/// Транспорт между watcher'ом, writer'ом БД и внешними потребителями.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageBusKind {
+    #[default]
+    #[serde(rename = "rabbitmq")]
+    RabbitMq,
+    /// Внутри процесса, без брокера: для запуска одним бинарником и тестов.
+    InMemory,
+}
+
+/// Куда watcher отправляет аналитику блоков.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineTopology {
+    /// watcher -> mining-analytics -> БД.
+    #[default]
+    Queue,
+    /// watcher -> БД, без брокера.
+    Direct,
+    /// В БД пишет сам watcher, а mining-analytics остаётся для внешних потребителей.
+    Both,
+}
+
+impl PipelineTopology {
+    pub fn publishes_to_queue(&self) -> bool {
+        matches!(self, PipelineTopology::Queue | PipelineTopology::Both)
+    }
+
+    pub fn writes_to_database(&self) -> bool {
+        matches!(self, PipelineTopology::Direct | PipelineTopology::Both)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BitcoinCoreRpcConfig {
+    url: String,
+    cookie_file: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    #[serde(default = "default_latest_blocks_count")]
+    latest_blocks_count: u64,
+}
+
+fn default_latest_blocks_count() -> u64 {
+    10
+}
+
+/// Историческая загрузка блоков: идём от `to_height` (или текущей вершины) вниз до `from_height` (или genesis).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillConfig {
+    #[serde(default)]
+    enabled: bool,
+    from_height: Option<u64>,
+    to_height: Option<u64>,
+    #[serde(default = "default_backfill_page_delay_ms")]
+    page_delay_ms: u64,
+    #[serde(default = "default_backfill_max_block_attempts")]
+    max_block_attempts: u32,
+}
+
+/// Правила уведомлений в стрим `mining-notifications`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    #[serde(default = "default_true")]
+    enabled: bool,
+    /// Slug'и пулов, о каждом блоке которых нужно сообщать.
+    #[serde(default)]
+    watched_pools: Vec<String>,
+    #[serde(default = "default_dominance_window_blocks")]
+    dominance_window_blocks: usize,
+    /// Доля блоков в окне (0..1), начиная с которой пул считается доминирующим.
+    #[serde(default = "default_dominance_threshold")]
+    dominance_threshold: f64,
+    #[serde(default = "default_long_block_interval_secs")]
+    long_block_interval_secs: u64,
+    #[serde(default = "default_min_reorg_depth")]
+    min_reorg_depth: usize,
+    #[serde(default = "default_true")]
+    empty_blocks: bool,
+    /// По умолчанию выключено: без `pools_file` неизвестен любой пул, и правило срабатывало бы на каждый блок.
+    #[serde(default)]
+    unknown_miners: bool,
+    #[serde(default = "default_true")]
+    reward_anomalies: bool,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            watched_pools: Vec::new(),
+            dominance_window_blocks: default_dominance_window_blocks(),
+            dominance_threshold: default_dominance_threshold(),
+            long_block_interval_secs: default_long_block_interval_secs(),
+            min_reorg_depth: default_min_reorg_depth(),
+            empty_blocks: true,
+            unknown_miners: false,
+            reward_anomalies: true,
+        }
+    }
+}
+
+/// Перезапуск упавших фоновых задач.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorConfig {
+    #[serde(default = "default_restart_initial_delay_ms")]
+    restart_initial_delay_ms: u64,
+    #[serde(default = "default_restart_max_delay_ms")]
+    restart_max_delay_ms: u64,
+    /// `None` — перезапускать без ограничений.
+    #[serde(default)]
+    max_restarts: Option<u32>,
+    /// Как часто писать в лог статусы задач.
+    #[serde(default = "default_status_log_interval_secs")]
+    status_log_interval_secs: u64,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            restart_initial_delay_ms: default_restart_initial_delay_ms(),
+            restart_max_delay_ms: default_restart_max_delay_ms(),
+            max_restarts: None,
+            status_log_interval_secs: default_status_log_interval_secs(),
+        }
+    }
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_restart_initial_delay_ms() -> u64 {
+    1000
+}
+
+fn default_restart_max_delay_ms() -> u64 {
+    60_000
+}
+
+fn default_status_log_interval_secs() -> u64 {
+    300
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_dominance_window_blocks() -> usize {
+    144
+}
+
+fn default_dominance_threshold() -> f64 {
+    0.4
+}
+
+fn default_long_block_interval_secs() -> u64 {
+    3600
+}
+
+fn default_min_reorg_depth() -> usize {
+    1
+}
+
+fn default_max_reorg_depth() -> u64 {
+    100
+}
+
+fn default_state_dir() -> String {
+    "./state".to_string()
+}
+
+fn default_backfill_page_delay_ms() -> u64 {
+    1000
+}
+
+fn default_backfill_max_block_attempts() -> u32 {
+    3
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RabbitMqConfig {
     host: String,
     port: u16,
     username: Option<String>,
     password: Option<String>,
-    stream_name: String,
+    /// Прежний параметр, который сервис никогда не читал. Конфиг с ним не загружается,
+    /// чтобы не переключиться молча на стрим по умолчанию: имя стрима задаётся в `streams.analytics`.
+    #[serde(default, skip_serializing, deserialize_with = "reject_stream_name")]
+    #[allow(dead_code)]
+    stream_name: (),
+    /// Имена и retention стримов. Разные имена позволяют нескольким окружениям делить один брокер.
+    #[serde(default)]
+    streams: StreamsConfig,
+    #[serde(default = "default_batch_size")]
+    batch_size: usize,
+    /// Сколько максимум держим неполный батч, прежде чем отправить.
+    #[serde(default = "default_batch_max_latency_ms")]
+    batch_max_latency_ms: u64,
+    /// После стольких неудачных попыток батч уходит в spool на диске.
+    #[serde(default = "default_max_send_attempts")]
+    max_send_attempts: u32,
+    #[serde(default = "default_retry_initial_delay_ms")]
+    retry_initial_delay_ms: u64,
+    #[serde(default = "default_retry_max_delay_ms")]
+    retry_max_delay_ms: u64,
+    /// После стольких отказов БД записать сообщение `mining-analytics` оно уходит в `mining-analytics-dlq`.
+    /// Недоступность БД отказом не считается.
+    #[serde(default = "default_max_db_write_attempts")]
+    max_db_write_attempts: u32,
+    /// Формат публикуемых `mining-analytics`; читатель определяет формат по content-type каждого сообщения.
+    #[serde(default)]
+    codec: Codec,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamsConfig {
+    #[serde(default = "default_analytics_stream")]
+    analytics: String,
+    #[serde(default = "default_notifications_stream")]
+    notifications: String,
+    /// По умолчанию `{analytics}-dlq`.
+    #[serde(default)]
+    dead_letter: Option<String>,
+    /// Основной producer стрима аналитики, по умолчанию `{analytics}-producer`.
+    /// Backfill пишет через producer'ы `{analytics}-backfill_<from>_<to>`.
+    #[serde(default)]
+    producer_name: Option<String>,
+    /// Consumer, который читает стрим аналитики в PostgreSQL и хранит под этим именем offset.
+    #[serde(default = "default_consumer_name")]
+    consumer_name: String,
+    /// Retention применяется только при создании стрима: у существующего стрима брокер его не меняет.
+    #[serde(default = "default_max_length_bytes")]
+    max_length_bytes: Option<u64>,
+    #[serde(default)]
+    max_age_secs: Option<u64>,
+    #[serde(default)]
+    max_segment_size_bytes: Option<u64>,
+    /// Больше 1 — аналитика публикуется в обычные стримы `{analytics}-0 .. {analytics}-{N-1}` по хешу slug'а пула (эмуляция super stream'а).
+    /// Смена значения переносит пулы в другие стримы.
+    #[serde(default = "default_partitions")]
+    partitions: u32,
+    /// Каждую партицию читает только один экземпляр сервиса: он держит advisory lock в PostgreSQL (эмуляция single active consumer).
+    #[serde(default)]
+    single_active_consumer: bool,
+}
+
+impl Default for StreamsConfig {
+    fn default() -> Self {
+        Self {
+            analytics: default_analytics_stream(),
+            notifications: default_notifications_stream(),
+            dead_letter: None,
+            producer_name: None,
+            consumer_name: default_consumer_name(),
+            max_length_bytes: default_max_length_bytes(),
+            max_age_secs: None,
+            max_segment_size_bytes: None,
+            partitions: default_partitions(),
+            single_active_consumer: false,
+        }
+    }
+}
+
+fn reject_stream_name<'de, D: Deserializer<'de>>(_: D) -> Result<(), D::Error> {
+    Err(D::Error::custom("rabbitmq_config.stream_name is no longer supported, set rabbitmq_config.streams.analytics instead"))
+}
+
+fn default_analytics_stream() -> String {
+    "mining-analytics".to_string()
+}
+
+fn default_notifications_stream() -> String {
+    "mining-notifications".to_string()
+}
+
+fn default_consumer_name() -> String {
+    "reader-for-block-analytics".to_string()
+}
+
+fn default_max_length_bytes() -> Option<u64> {
+    Some(5_000_000_000)
+}
+
+fn default_partitions() -> u32 {
+    1
+}
+
+fn default_batch_size() -> usize {
+    10
+}
+
+fn default_batch_max_latency_ms() -> u64 {
+    5000
+}
+
+fn default_max_send_attempts() -> u32 {
+    5
+}
+
+fn default_max_db_write_attempts() -> u32 {
+    3
+}
+
+fn default_retry_initial_delay_ms() -> u64 {
+    500
+}
+
+fn default_retry_max_delay_ms() -> u64 {
+    30_000
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +366,62 @@ pub struct DatabaseConfig {
     url: String
 }
 
+impl NotificationsConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn get_watched_pools(&self) -> &[String] {
+        &self.watched_pools
+    }
+
+    pub fn get_dominance_window_blocks(&self) -> usize {
+        self.dominance_window_blocks
+    }
+
+    pub fn get_dominance_threshold(&self) -> f64 {
+        self.dominance_threshold
+    }
+
+    pub fn get_long_block_interval_secs(&self) -> u64 {
+        self.long_block_interval_secs
+    }
+
+    pub fn get_min_reorg_depth(&self) -> usize {
+        self.min_reorg_depth
+    }
+
+    pub fn is_empty_blocks_enabled(&self) -> bool {
+        self.empty_blocks
+    }
+
+    pub fn is_unknown_miners_enabled(&self) -> bool {
+        self.unknown_miners
+    }
+
+    pub fn is_reward_anomalies_enabled(&self) -> bool {
+        self.reward_anomalies
+    }
+}
+
+impl SupervisorConfig {
+    pub fn get_restart_initial_delay_ms(&self) -> u64 {
+        self.restart_initial_delay_ms
+    }
+
+    pub fn get_restart_max_delay_ms(&self) -> u64 {
+        self.restart_max_delay_ms
+    }
+
+    pub fn get_max_restarts(&self) -> Option<u32> {
+        self.max_restarts
+    }
+
+    pub fn get_status_log_interval_secs(&self) -> u64 {
+        self.status_log_interval_secs
+    }
+}
+
 impl RabbitMqConfig {
     pub fn get_host(&self) -> &str {
         &self.host
@@ -43,8 +439,122 @@ impl RabbitMqConfig {
         self.password.as_ref()
     }
 
-    pub fn get_stream_name(&self) -> &str {
-        &self.stream_name
+    pub fn get_streams(&self) -> &StreamsConfig {
+        &self.streams
+    }
+
+    pub fn get_batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    pub fn get_batch_max_latency_ms(&self) -> u64 {
+        self.batch_max_latency_ms
+    }
+
+    pub fn get_max_send_attempts(&self) -> u32 {
+        self.max_send_attempts
+    }
+
+    pub fn get_retry_initial_delay_ms(&self) -> u64 {
+        self.retry_initial_delay_ms
+    }
+
+    pub fn get_retry_max_delay_ms(&self) -> u64 {
+        self.retry_max_delay_ms
+    }
+
+    pub fn get_max_db_write_attempts(&self) -> u32 {
+        self.max_db_write_attempts
+    }
+
+    pub fn get_codec(&self) -> Codec {
+        self.codec
+    }
+}
+
+impl StreamsConfig {
+    pub fn get_analytics(&self) -> &str {
+        &self.analytics
+    }
+
+    pub fn get_notifications(&self) -> &str {
+        &self.notifications
+    }
+
+    pub fn get_dead_letter(&self) -> String {
+        self.dead_letter.clone().unwrap_or_else(|| format!("{}-dlq", self.analytics))
+    }
+
+    pub fn get_producer_name(&self) -> String {
+        self.producer_name.clone().unwrap_or_else(|| format!("{}-producer", self.analytics))
+    }
+
+    pub fn get_consumer_name(&self) -> &str {
+        &self.consumer_name
+    }
+
+    pub fn get_max_length_bytes(&self) -> Option<u64> {
+        self.max_length_bytes
+    }
+
+    pub fn get_max_age_secs(&self) -> Option<u64> {
+        self.max_age_secs
+    }
+
+    pub fn get_max_segment_size_bytes(&self) -> Option<u64> {
+        self.max_segment_size_bytes
+    }
+
+    pub fn get_partitions(&self) -> u32 {
+        self.partitions
+    }
+
+    pub fn is_single_active_consumer(&self) -> bool {
+        self.single_active_consumer
+    }
+}
+
+impl BitcoinCoreRpcConfig {
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_cookie_file(&self) -> Option<&str> {
+        self.cookie_file.as_deref()
+    }
+
+    pub fn get_username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    pub fn get_password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+
+    pub fn get_latest_blocks_count(&self) -> u64 {
+        self.latest_blocks_count
+    }
+}
+
+impl BackfillConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn get_from_height(&self) -> Option<u64> {
+        self.from_height
+    }
+
+    pub fn get_to_height(&self) -> Option<u64> {
+        self.to_height
+    }
+
+    pub fn get_page_delay_ms(&self) -> u64 {
+        self.page_delay_ms
+    }
+
+    pub fn get_max_block_attempts(&self) -> u32 {
+        self.max_block_attempts
     }
 }
 
@@ -71,10 +581,58 @@ impl Config {
         &self.api_url
     }
 
+    pub fn get_chain_source(&self) -> ChainSourceKind {
+        self.chain_source
+    }
+
+    pub fn get_message_bus(&self) -> MessageBusKind {
+        self.message_bus
+    }
+
+    pub fn get_pipeline(&self) -> PipelineTopology {
+        self.pipeline
+    }
+
+    pub fn get_bitcoin_core_rpc_config(&self) -> Option<&BitcoinCoreRpcConfig> {
+        self.bitcoin_core_rpc.as_ref()
+    }
+
     pub fn get_interval_analytic_blocks(&self) -> u64 {
         self.interval_analytic_blocks
     }
 
+    pub fn get_pools_file(&self) -> Option<&str> {
+        self.pools_file.as_deref()
+    }
+
+    pub fn is_full_block_analysis_enabled(&self) -> bool {
+        self.full_block_analysis
+    }
+
+    pub fn get_max_reorg_depth(&self) -> u64 {
+        self.max_reorg_depth
+    }
+
+    pub fn get_state_dir(&self) -> &str {
+        &self.state_dir
+    }
+
+    pub fn get_backfill_config(&self) -> Option<&BackfillConfig> {
+        self.backfill.as_ref()
+    }
+
+    pub fn get_notifications_config(&self) -> &NotificationsConfig {
+        &self.notifications
+    }
+
+    pub fn get_supervisor_config(&self) -> &SupervisorConfig {
+        &self.supervisor
+    }
+
+    pub fn get_shutdown_timeout_secs(&self) -> u64 {
+        self.shutdown_timeout_secs
+    }
+
     pub fn get_rabbitmq_config(&self) -> &RabbitMqConfig {
         &self.rabbitmq_config
     }
@@ -82,4 +640,27 @@ impl Config {
     pub fn get_database_url(&self) -> &str {
         &self.database_config.url
     }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn rabbitmq_config(extra: serde_json::Value) -> serde_json::Result<RabbitMqConfig> {
+        let mut config = json!({ "host": "localhost", "port": 5552, "username": null, "password": null });
+        config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(config)
+    }
+
+    #[test]
+    fn rejects_the_old_stream_name() {
+        let err = rabbitmq_config(json!({ "stream_name": "any-stream-names" })).unwrap_err();
+        assert!(err.to_string().contains("streams.analytics"), "{}", err);
+
+        let config = rabbitmq_config(json!({ "streams": { "analytics": "staging-analytics" } })).unwrap();
+        assert_eq!(config.get_streams().get_analytics(), "staging-analytics");
+    }
 }
\ No newline at end of file
@@ -1,2 +1,5 @@
 pub mod block;
-pub mod transaction;
\ No newline at end of file
+pub mod transaction;
+pub mod fee_stats;
+pub mod reward_anomaly;
+pub mod notification;
\ No newline at end of file
@@ -18,6 +18,39 @@ pub struct Block {
 }
 
 impl Block {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: String,
+        height: u64,
+        version: u64,
+        timestamp: u64,
+        tx_count: u64,
+        size: u64,
+        weight: u64,
+        merkle_root: String,
+        previousblockhash: String,
+        mediantime: u64,
+        nonce: u64,
+        bits: u64,
+        difficulty: f64,
+    ) -> Self {
+        Self {
+            id,
+            height,
+            version,
+            timestamp,
+            tx_count,
+            size,
+            weight,
+            merkle_root,
+            previousblockhash,
+            mediantime,
+            nonce,
+            bits,
+            difficulty,
+        }
+    }
+
     pub fn get_id(&self) -> String {
         self.id.clone()
     }
@@ -26,6 +59,10 @@ impl Block {
         self.height as u32
     }
 
+    pub fn get_previous_block_hash(&self) -> &str {
+        &self.previousblockhash
+    }
+
     pub fn get_timestamp(&self) -> u64 {
         self.timestamp
     }
@@ -45,4 +82,14 @@ impl Block {
     pub fn get_tx_count(&self) -> u64 {
         self.tx_count
     }
+}
+
+/// Короткая ссылка на обработанный блок: хранится в окне последних блоков watcher'а и уходит в события reorg.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockRef {
+    pub height: u64,
+    pub hash: String,
+    pub guessed_miner: Option<String>,
+    #[serde(default)]
+    pub pool_slug: Option<String>,
 }
\ No newline at end of file
@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::transaction::Transaction;
+
+/// Статистика комиссий блока по полному списку транзакций (coinbase не учитывается).
+/// Feerate — в sat/vB, перцентили считаются по транзакциям (nearest-rank), без взвешивания по vsize.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockFeeStats {
+    pub total_fees: i64,
+    pub total_vsize: u64,
+    pub transactions_count: u64,
+    pub min_feerate: f64,
+    pub max_feerate: f64,
+    pub median_feerate: f64,
+    pub p10_feerate: f64,
+    pub p25_feerate: f64,
+    pub p75_feerate: f64,
+    pub p90_feerate: f64,
+}
+
+impl BlockFeeStats {
+    pub fn from_transactions(transactions: &[Transaction]) -> Result<Self> {
+        let mut total_fees = 0;
+        let mut total_vsize = 0;
+        let mut feerates = Vec::with_capacity(transactions.len());
+
+        for tx in transactions.iter().filter(|tx| !tx.is_coinbase()) {
+            let fee = tx.get_fee()
+                .ok_or_else(|| anyhow!("no fee for transaction {}", tx.get_txid()))?;
+            let vsize = tx.get_vsize();
+
+            total_fees += fee;
+            total_vsize += vsize as u64;
+            feerates.push(fee as f64 / vsize.max(1) as f64);
+        }
+
+        feerates.sort_by(f64::total_cmp);
+
+        Ok(Self {
+            total_fees,
+            total_vsize,
+            transactions_count: feerates.len() as u64,
+            min_feerate: feerates.first().copied().unwrap_or_default(),
+            max_feerate: feerates.last().copied().unwrap_or_default(),
+            median_feerate: percentile(&feerates, 50),
+            p10_feerate: percentile(&feerates, 10),
+            p25_feerate: percentile(&feerates, 25),
+            p75_feerate: percentile(&feerates, 75),
+            p90_feerate: percentile(&feerates, 90),
+        })
+    }
+}
+
+/// Nearest-rank перцентиль по отсортированному срезу. Для пустого блока — 0.
+fn percentile(sorted: &[f64], percent: usize) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::transaction::{Status, VectorInputs};
+
+    fn transaction(txid: &str, input: VectorInputs, fee: Option<i64>, vsize: u32) -> Transaction {
+        let status = Status::confirmed(800_000, "00".repeat(32), 1_690_000_000);
+        Transaction::new(txid.to_string(), 2, 0, vec![input], vec![], vsize, vsize * 4, 0, fee, status)
+    }
+
+    fn spend(txid: &str, fee: Option<i64>, vsize: u32) -> Transaction {
+        let input = VectorInputs::new("ab".repeat(32), 0, String::new(), String::new(), vec![], 0xffff_ffff);
+        transaction(txid, input, fee, vsize)
+    }
+
+    fn coinbase() -> Transaction {
+        transaction("coinbase", VectorInputs::coinbase("0300350c".to_string(), String::new(), vec![], 0xffff_ffff), None, 50)
+    }
+
+    #[test]
+    fn uses_nearest_rank_percentiles_without_coinbase() {
+        // Feerate 1..10 sat/vB вперемешку, coinbase первой.
+        let mut transactions = vec![coinbase()];
+        transactions.extend([7, 2, 10, 5, 1, 9, 3, 8, 6, 4].map(|feerate| spend(&format!("tx{}", feerate), Some(feerate * 100), 100)));
+
+        let stats = BlockFeeStats::from_transactions(&transactions).unwrap();
+
+        assert_eq!(stats.total_fees, 5_500);
+        assert_eq!(stats.total_vsize, 1_000);
+        assert_eq!(stats.transactions_count, 10);
+        assert_eq!(stats.min_feerate, 1.0);
+        assert_eq!(stats.max_feerate, 10.0);
+        // Ранг ceil(p * n / 100): 1, 3, 5, 8, 9.
+        assert_eq!(stats.p10_feerate, 1.0);
+        assert_eq!(stats.p25_feerate, 3.0);
+        assert_eq!(stats.median_feerate, 5.0);
+        assert_eq!(stats.p75_feerate, 8.0);
+        assert_eq!(stats.p90_feerate, 9.0);
+    }
+
+    #[test]
+    fn single_transaction_is_every_percentile() {
+        let stats = BlockFeeStats::from_transactions(&[coinbase(), spend("tx", Some(1_410), 141)]).unwrap();
+
+        assert_eq!(stats.p10_feerate, 10.0);
+        assert_eq!(stats.median_feerate, 10.0);
+        assert_eq!(stats.p90_feerate, 10.0);
+    }
+
+    #[test]
+    fn block_with_only_coinbase_has_zero_stats() {
+        let stats = BlockFeeStats::from_transactions(&[coinbase()]).unwrap();
+
+        assert_eq!(stats, BlockFeeStats {
+            total_fees: 0,
+            total_vsize: 0,
+            transactions_count: 0,
+            min_feerate: 0.0,
+            max_feerate: 0.0,
+            median_feerate: 0.0,
+            p10_feerate: 0.0,
+            p25_feerate: 0.0,
+            p75_feerate: 0.0,
+            p90_feerate: 0.0,
+        });
+    }
+
+    #[test]
+    fn unknown_fee_is_an_error() {
+        assert!(BlockFeeStats::from_transactions(&[coinbase(), spend("tx", None, 100)]).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+use crate::domain::block::BlockRef;
+use crate::domain::reward_anomaly::RewardAnomaly;
+
+/// События стрима `mining-notifications`. Тип события лежит в поле `type`,
+/// схема — `schemas/mining-notifications.schema.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Notification {
+    /// Блок от пула из `watched_pools`.
+    NewBlock {
+        height: u64,
+        block_hash: String,
+        timestamp: u64,
+        pool_name: String,
+        pool_slug: String,
+    },
+    /// Доля пула в окне последних блоков поднялась до порога.
+    PoolDominance {
+        height: u64,
+        pool_name: String,
+        pool_slug: String,
+        pool_blocks: usize,
+        window_blocks: usize,
+        share: f64,
+        threshold: f64,
+    },
+    /// В блоке только coinbase.
+    EmptyBlock {
+        height: u64,
+        block_hash: String,
+        pool_slug: String,
+    },
+    Reorg {
+        fork_height: u64,
+        fork_hash: String,
+        depth: usize,
+        orphaned_blocks: Vec<BlockRef>,
+        new_blocks: Vec<BlockRef>,
+    },
+    /// Между соседними блоками прошло больше порога (по времени из заголовков).
+    LongBlockInterval {
+        height: u64,
+        block_hash: String,
+        interval_secs: u64,
+        threshold_secs: u64,
+    },
+    /// Пул не определился ни по адресу, ни по тегу.
+    UnknownMiner {
+        height: u64,
+        block_hash: String,
+        guessed_miner: String,
+        miner_address: Option<String>,
+    },
+    RewardAnomaly(RewardAnomaly),
+}
\ No newline at end of file
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use crate::utils::block_reward::BlockRewardCalculator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RewardAnomalyKind {
+    /// Майнер забрал меньше, чем субсидия плюс комиссии.
+    UnderClaimed,
+    /// Coinbase больше субсидии плюс комиссий: такой блок невалиден, значит, данные не сходятся.
+    OverClaimed,
+}
+
+impl RewardAnomalyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RewardAnomalyKind::UnderClaimed => "under_claimed",
+            RewardAnomalyKind::OverClaimed => "over_claimed",
+        }
+    }
+}
+
+/// Расхождение между ожидаемой наградой за блок и суммой выходов coinbase.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RewardAnomaly {
+    pub height: u64,
+    pub block_hash: String,
+    pub kind: RewardAnomalyKind,
+    pub expected_subsidy: i64,
+    /// `None`, если блок не разбирался целиком и комиссии неизвестны.
+    pub fees: Option<i64>,
+    pub coinbase_total: i64,
+    /// Ожидаемая награда минус coinbase: положительная — недобор, отрицательная — перебор.
+    pub difference: i64,
+}
+
+impl RewardAnomaly {
+    /// Без комиссий можно поймать только недобор субсидии: комиссии не бывают отрицательными.
+    pub fn detect(height: u64, block_hash: &str, coinbase_total: i64, fees: Option<i64>) -> Option<Self> {
+        let expected_subsidy = BlockRewardCalculator::calculate_block_reward(height as i64);
+        let expected_total = expected_subsidy + fees.unwrap_or(0);
+        let difference = expected_total - coinbase_total;
+
+        let kind = match (difference, fees) {
+            (difference, _) if difference > 0 => RewardAnomalyKind::UnderClaimed,
+            (difference, Some(_)) if difference < 0 => RewardAnomalyKind::OverClaimed,
+            _ => return None,
+        };
+
+        Some(Self {
+            height,
+            block_hash: block_hash.to_string(),
+            kind,
+            expected_subsidy,
+            fees,
+            coinbase_total,
+            difference,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Субсидия на высоте 800000 — 6.25 BTC.
+    const HEIGHT: u64 = 800_000;
+    const SUBSIDY: i64 = 625_000_000;
+
+    #[test]
+    fn flags_under_claimed_reward_with_known_fees() {
+        let anomaly = RewardAnomaly::detect(HEIGHT, "hash", SUBSIDY + 10_000, Some(25_000)).unwrap();
+
+        assert_eq!(anomaly.kind, RewardAnomalyKind::UnderClaimed);
+        assert_eq!(anomaly.expected_subsidy, SUBSIDY);
+        assert_eq!(anomaly.fees, Some(25_000));
+        assert_eq!(anomaly.coinbase_total, SUBSIDY + 10_000);
+        assert_eq!(anomaly.difference, 15_000);
+        assert_eq!(anomaly.block_hash, "hash");
+    }
+
+    #[test]
+    fn flags_under_claimed_subsidy_without_fees() {
+        let anomaly = RewardAnomaly::detect(HEIGHT, "hash", SUBSIDY - 1, None).unwrap();
+
+        assert_eq!(anomaly.kind, RewardAnomalyKind::UnderClaimed);
+        assert_eq!(anomaly.fees, None);
+        assert_eq!(anomaly.difference, 1);
+    }
+
+    #[test]
+    fn flags_over_claimed_reward_only_with_known_fees() {
+        let anomaly = RewardAnomaly::detect(HEIGHT, "hash", SUBSIDY + 30_000, Some(25_000)).unwrap();
+        assert_eq!(anomaly.kind, RewardAnomalyKind::OverClaimed);
+        assert_eq!(anomaly.difference, -5_000);
+
+        // Без комиссий остаток сверх субсидии — это комиссии, а не перебор.
+        assert_eq!(RewardAnomaly::detect(HEIGHT, "hash", SUBSIDY + 30_000, None), None);
+    }
+
+    #[test]
+    fn exact_reward_is_not_an_anomaly() {
+        assert_eq!(RewardAnomaly::detect(HEIGHT, "hash", SUBSIDY + 25_000, Some(25_000)), None);
+        assert_eq!(RewardAnomaly::detect(HEIGHT, "hash", SUBSIDY, None), None);
+        // Генезис-эпоха: 50 BTC.
+        assert_eq!(RewardAnomaly::detect(1, "hash", 5_000_000_000, Some(0)), None);
+    }
+}
\ No newline at end of file
@@ -1,13 +1,20 @@
 use serde::{Deserialize, Serialize};
 use crate::utils::block_reward::BlockRewardCalculator;
 
+/// Coinbase ссылается на "нулевой" выход.
+const COINBASE_PREVOUT_TXID: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const COINBASE_PREVOUT_VOUT: u64 = 0xffff_ffff;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VectorInputs {
     txid: String,
     vout: u64,
-    prevout: Option<String>,
+    /// Выход, который тратит вход. mempool.space отдаёт его объектом, у coinbase — `null`.
+    prevout: Option<VectorOutputs>,
     scriptsig: String,
     scriptsig_asm: String,
+    /// У не-segwit транзакций mempool.space поле не присылает.
+    #[serde(default)]
     witness: Vec<String>,
     pub is_coinbase: bool,
     sequence: u64,
@@ -40,10 +47,33 @@ pub struct Transaction {
     size: u32,
     weight: u32,
     sigops: u32,
+    /// Комиссия в сатоши, если backend её отдаёт.
+    #[serde(default)]
+    fee: Option<i64>,
     status: Status
 }
 
 impl Transaction {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        txid: String,
+        version: u64,
+        locktime: u64,
+        vin: Vec<VectorInputs>,
+        vout: Vec<VectorOutputs>,
+        size: u32,
+        weight: u32,
+        sigops: u32,
+        fee: Option<i64>,
+        status: Status,
+    ) -> Self {
+        Self { txid, version, locktime, vin, vout, size, weight, sigops, fee, status }
+    }
+
+    pub fn get_txid(&self) -> &str {
+        &self.txid
+    }
+
     pub fn get_main_reward_vout(&self) -> Option<&VectorOutputs> {
         self.vout.iter()
             .filter(|vout| vout.value > 0) // Исключаем OP_RETURN (value = 0)
@@ -76,7 +106,6 @@ impl Transaction {
             .collect()
     }
 
-    #[allow(dead_code)]
     pub fn get_vouts(&self) -> &Vec<VectorOutputs> {
         &self.vout
     }
@@ -98,6 +127,30 @@ impl Transaction {
         &self.status
     }
 
+    /// Комиссия транзакции: из ответа backend'а, либо как разница входов и выходов, если известны все prevout.
+    pub fn get_fee(&self) -> Option<i64> {
+        if self.is_coinbase() {
+            return Some(0);
+        }
+
+        self.fee.or_else(|| {
+            let inputs_value = self.vin.iter()
+                .map(|vin| vin.prevout.as_ref().map(|prevout| prevout.value))
+                .sum::<Option<i64>>()?;
+
+            Some(inputs_value - self.get_full_reward_value())
+        })
+    }
+
+    /// Виртуальный размер (BIP141): weight / 4 с округлением вверх.
+    pub fn get_vsize(&self) -> u32 {
+        self.weight.div_ceil(4)
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.vin.first().is_some_and(|vin| vin.is_coinbase)
+    }
+
     pub fn calculate_fee(&self) -> Option<i64> {
         if self.vin.is_empty() || !self.vin[0].is_coinbase {
             return None;
@@ -118,7 +171,32 @@ impl Transaction {
     }
 }
 
+impl Status {
+    /// Статус транзакции из блока, уже попавшего в цепочку.
+    pub fn confirmed(block_height: i64, block_hash: String, block_time: u64) -> Self {
+        Self { confirmed: true, block_height, block_hash, block_time }
+    }
+}
+
 impl VectorOutputs {
+    pub fn new(
+        scriptpubkey: String,
+        scriptpubkey_asm: String,
+        scriptpubkey_type: String,
+        scriptpubkey_address: Option<String>,
+        value: i64,
+    ) -> Self {
+        Self { scriptpubkey, scriptpubkey_asm, scriptpubkey_type, scriptpubkey_address, value }
+    }
+
+    pub fn get_scriptpubkey(&self) -> &str {
+        &self.scriptpubkey
+    }
+
+    pub fn get_scriptpubkey_type(&self) -> &str {
+        &self.scriptpubkey_type
+    }
+
     pub fn get_scriptpubkey_address(&self) -> &Option<String> {
         &self.scriptpubkey_address
     }
@@ -129,6 +207,25 @@ impl VectorOutputs {
 }
 
 impl VectorInputs {
+    /// Обычный вход. Prevout неизвестен: его заполняет только mempool.space.
+    pub fn new(txid: String, vout: u64, scriptsig: String, scriptsig_asm: String, witness: Vec<String>, sequence: u64) -> Self {
+        Self { txid, vout, prevout: None, scriptsig, scriptsig_asm, witness, is_coinbase: false, sequence }
+    }
+
+    /// Вход coinbase: ссылается на нулевой txid и vout 0xffffffff.
+    pub fn coinbase(scriptsig: String, scriptsig_asm: String, witness: Vec<String>, sequence: u64) -> Self {
+        Self {
+            txid: COINBASE_PREVOUT_TXID.to_string(),
+            vout: COINBASE_PREVOUT_VOUT,
+            prevout: None,
+            scriptsig,
+            scriptsig_asm,
+            witness,
+            is_coinbase: true,
+            sequence,
+        }
+    }
+
     pub fn get_vin_scriptsig(&self) -> &str {
         &self.scriptsig
     }
@@ -1,3 +1,4 @@
 pub mod collector;
 pub mod queue;
-pub mod db;
\ No newline at end of file
+pub mod db;
+pub mod state;
\ No newline at end of file
@@ -1,2 +1,4 @@
 pub mod namespace;
-pub mod mempool;
\ No newline at end of file
+pub mod mempool;
+pub mod chain_source;
+pub mod bitcoin_core;
\ No newline at end of file
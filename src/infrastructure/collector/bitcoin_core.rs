@@ -0,0 +1,536 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bitcoin::ScriptBuf;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::config::config::BitcoinCoreRpcConfig;
+use crate::domain::block::Block;
+use crate::domain::transaction::{Status, Transaction, VectorInputs, VectorOutputs};
+use crate::infrastructure::collector::chain_source::ChainSource;
+
+const SATOSHI_PER_BTC: f64 = 100_000_000.0;
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcBlockchainInfo {
+    blocks: u64,
+}
+
+/// Ответ `getblock`: при verbosity 1 `tx` содержит txid, при verbosity 2 — полные транзакции.
+#[derive(Debug, Deserialize)]
+struct RpcBlock<T> {
+    hash: String,
+    height: u64,
+    version: u64,
+    time: u64,
+    mediantime: u64,
+    nonce: u64,
+    bits: String,
+    difficulty: f64,
+    merkleroot: String,
+    #[serde(rename = "nTx")]
+    n_tx: u64,
+    size: u64,
+    weight: u64,
+    previousblockhash: Option<String>,
+    tx: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcTransaction {
+    txid: String,
+    version: u64,
+    locktime: u64,
+    size: u32,
+    weight: u32,
+    /// Есть только в `getblock` с verbosity 2 и только у не-coinbase транзакций.
+    fee: Option<f64>,
+    vin: Vec<RpcVectorInput>,
+    vout: Vec<RpcVectorOutput>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcVectorInput {
+    coinbase: Option<String>,
+    txid: Option<String>,
+    vout: Option<u64>,
+    #[serde(rename = "scriptSig")]
+    script_sig: Option<RpcScript>,
+    txinwitness: Option<Vec<String>>,
+    sequence: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcVectorOutput {
+    value: f64,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: RpcScriptPubKey,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcScript {
+    asm: String,
+    hex: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcScriptPubKey {
+    asm: String,
+    hex: String,
+    #[serde(rename = "type")]
+    script_type: String,
+    address: Option<String>,
+}
+
+impl<T> RpcBlock<T> {
+    fn to_block(&self) -> Result<Block> {
+        let bits = u64::from_str_radix(&self.bits, 16)
+            .map_err(|e| anyhow!("bad bits {} in block {}: {}", self.bits, self.hash, e))?;
+
+        Ok(Block::new(
+            self.hash.clone(),
+            self.height,
+            self.version,
+            self.time,
+            self.n_tx,
+            self.size,
+            self.weight,
+            self.merkleroot.clone(),
+            self.previousblockhash.clone().unwrap_or_default(),
+            self.mediantime,
+            self.nonce,
+            bits,
+            self.difficulty,
+        ))
+    }
+
+    fn to_status(&self) -> Status {
+        Status::confirmed(self.height as i64, self.hash.clone(), self.time)
+    }
+}
+
+impl RpcTransaction {
+    fn into_transaction(self, status: Status) -> Transaction {
+        Transaction::new(
+            self.txid,
+            self.version,
+            self.locktime,
+            self.vin.into_iter().map(RpcVectorInput::into_vector_input).collect(),
+            self.vout.into_iter().map(RpcVectorOutput::into_vector_output).collect(),
+            self.size,
+            self.weight,
+            // Bitcoin Core не отдаёт sigops в getrawtransaction/getblock.
+            0,
+            self.fee.map(btc_to_sats),
+            status,
+        )
+    }
+}
+
+impl RpcVectorInput {
+    fn into_vector_input(self) -> VectorInputs {
+        let witness = self.txinwitness.unwrap_or_default();
+
+        match self.coinbase {
+            Some(coinbase_hex) => {
+                let scriptsig_asm = ScriptBuf::from_hex(&coinbase_hex)
+                    .map(|script| script.to_asm_string())
+                    .unwrap_or_default();
+
+                VectorInputs::coinbase(coinbase_hex, scriptsig_asm, witness, self.sequence)
+            }
+            None => {
+                let (scriptsig_asm, scriptsig) = self.script_sig
+                    .map(|script| (script.asm, script.hex))
+                    .unwrap_or_default();
+
+                VectorInputs::new(
+                    self.txid.unwrap_or_default(),
+                    self.vout.unwrap_or_default(),
+                    scriptsig,
+                    scriptsig_asm,
+                    witness,
+                    self.sequence,
+                )
+            }
+        }
+    }
+}
+
+impl RpcVectorOutput {
+    fn into_vector_output(self) -> VectorOutputs {
+        VectorOutputs::new(
+            self.script_pubkey.hex,
+            self.script_pubkey.asm,
+            to_mempool_script_type(&self.script_pubkey.script_type).to_string(),
+            self.script_pubkey.address,
+            btc_to_sats(self.value),
+        )
+    }
+}
+
+fn btc_to_sats(value: f64) -> i64 {
+    (value * SATOSHI_PER_BTC).round() as i64
+}
+
+/// Приводим типы скриптов Bitcoin Core к названиям mempool.space, чтобы downstream не зависел от backend'а.
+fn to_mempool_script_type(script_type: &str) -> &str {
+    match script_type {
+        "pubkey" => "p2pk",
+        "pubkeyhash" => "p2pkh",
+        "scripthash" => "p2sh",
+        "witness_v0_keyhash" => "v0_p2wpkh",
+        "witness_v0_scripthash" => "v0_p2wsh",
+        "witness_v1_taproot" => "v1_p2tr",
+        "nulldata" => "op_return",
+        "nonstandard" => "unknown",
+        other => other,
+    }
+}
+
+pub struct BitcoinCoreClient {
+    client: Arc<Client>,
+    config: BitcoinCoreRpcConfig,
+    request_id: AtomicU64,
+}
+
+impl BitcoinCoreClient {
+    pub fn new(client: Arc<Client>, config: &BitcoinCoreRpcConfig) -> Self {
+        Self {
+            client,
+            config: config.clone(),
+            request_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Cookie перечитывается на каждый запрос: bitcoind генерирует новый при каждом рестарте.
+    fn credentials(&self) -> Result<Option<(String, String)>> {
+        if let Some(cookie_file) = self.config.get_cookie_file() {
+            let cookie = std::fs::read_to_string(cookie_file)
+                .map_err(|e| anyhow!("Couldn't read RPC cookie file {}: {}", cookie_file, e))?;
+            let (username, password) = cookie.trim()
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Bad RPC cookie format in {}", cookie_file))?;
+
+            return Ok(Some((username.to_string(), password.to_string())));
+        }
+
+        Ok(self.config.get_username()
+            .map(|username| (username.to_string(), self.config.get_password().unwrap_or_default().to_string())))
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        let id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+
+        let mut request = self.client.post(self.config.get_url()).json(&body);
+        if let Some((username, password)) = self.credentials()? {
+            request = request.basic_auth(username, Some(password));
+        }
+
+        // bitcoind отвечает 500 с JSON-телом на ошибки RPC, поэтому статус не проверяем, а разбираем тело.
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        let rpc_response: RpcResponse<T> = serde_json::from_str(&body)
+            .map_err(|e| anyhow!("Bad {} response (HTTP {}): {}", method, status, e))?;
+
+        if let Some(error) = rpc_response.error {
+            return Err(anyhow!("RPC {} failed with code {}: {}", method, error.code, error.message));
+        }
+
+        rpc_response.result.ok_or_else(|| anyhow!("RPC {} returned empty result", method))
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String> {
+        self.call("getblockhash", json!([height])).await
+    }
+
+    async fn get_block_header_info(&self, block_hash: &str) -> Result<RpcBlock<String>> {
+        self.call("getblock", json!([block_hash, 1])).await
+    }
+}
+
+#[async_trait]
+impl ChainSource for BitcoinCoreClient {
+    fn name(&self) -> &'static str {
+        "bitcoin_core"
+    }
+
+    async fn fetch_latest_blocks(&self) -> Result<Vec<Block>> {
+        let blockchain_info: RpcBlockchainInfo = self.call("getblockchaininfo", json!([])).await?;
+
+        self.fetch_blocks_from_height(blockchain_info.blocks).await
+    }
+
+    async fn fetch_blocks_from_height(&self, height: u64) -> Result<Vec<Block>> {
+        let count = self.config.get_latest_blocks_count().min(height + 1);
+
+        let mut blocks = Vec::with_capacity(count as usize);
+        for height in (height + 1 - count..=height).rev() {
+            blocks.push(self.fetch_block_by_height(height).await?);
+        }
+
+        Ok(blocks)
+    }
+
+    async fn fetch_block_by_hash(&self, block_hash: &str) -> Result<Block> {
+        self.get_block_header_info(block_hash).await?.to_block()
+    }
+
+    async fn fetch_block_by_height(&self, height: u64) -> Result<Block> {
+        let block_hash = self.get_block_hash(height).await?;
+
+        self.fetch_block_by_hash(&block_hash).await
+    }
+
+    async fn fetch_coinbase(&self, block_hash: &str) -> Result<Transaction> {
+        let block = self.get_block_header_info(block_hash).await?;
+        let coinbase_txid = block.tx.first()
+            .ok_or_else(|| anyhow!("block has no transactions"))?;
+
+        // С blockhash getrawtransaction работает и без -txindex.
+        let coinbase: RpcTransaction = self.call("getrawtransaction", json!([coinbase_txid, true, block_hash])).await?;
+        let coinbase_tx = coinbase.into_transaction(block.to_status());
+
+        let is_coinbase = coinbase_tx.get_vin_by_id(0)
+            .is_some_and(|vin| vin.is_coinbase);
+        if is_coinbase {
+            Ok(coinbase_tx)
+        } else {
+            Err(anyhow!("it isn't coinbase"))
+        }
+    }
+
+    async fn fetch_block_transactions(&self, block_hash: &str) -> Result<Vec<Transaction>> {
+        let mut block: RpcBlock<RpcTransaction> = self.call("getblock", json!([block_hash, 2])).await?;
+        let status = block.to_status();
+
+        let transactions = std::mem::take(&mut block.tx)
+            .into_iter()
+            .map(|tx| tx.into_transaction(status.clone()))
+            .collect();
+
+        Ok(transactions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    const TIP_HEIGHT: u64 = 800_002;
+    const COINBASE_TAG_HEX: &str = "0342340c122f466f756e6472792055534120506f6f6c2f";
+
+    fn stub_block_hash(height: u64) -> String {
+        format!("{:064x}", height)
+    }
+
+    fn stub_coinbase(block_hash: &str) -> Value {
+        json!({
+            "txid": format!("cb{}", &block_hash[2..]),
+            "version": 2,
+            "locktime": 0,
+            "size": 200,
+            "weight": 680,
+            "vin": [{"coinbase": COINBASE_TAG_HEX, "txinwitness": ["00".repeat(32)], "sequence": 4294967295u64}],
+            "vout": [
+                {"value": 3.15625, "n": 0, "scriptPubKey": {"asm": "0 11", "hex": format!("0014{}", "11".repeat(20)), "type": "witness_v0_keyhash", "address": "bc1qpool"}},
+                {"value": 0.0, "n": 1, "scriptPubKey": {"asm": "OP_RETURN", "hex": format!("6a24aa21a9ed{}", "22".repeat(32)), "type": "nulldata"}}
+            ]
+        })
+    }
+
+    fn stub_spend() -> Value {
+        json!({
+            "txid": "ff".repeat(32),
+            "version": 2,
+            "locktime": 0,
+            "size": 222,
+            "weight": 561,
+            "fee": 0.0000141,
+            "vin": [{"txid": "ee".repeat(32), "vout": 1, "scriptSig": {"asm": "", "hex": ""}, "txinwitness": ["00"], "sequence": 4294967293u64}],
+            "vout": [{"value": 0.1, "n": 0, "scriptPubKey": {"asm": "0 33", "hex": format!("0014{}", "33".repeat(20)), "type": "witness_v0_keyhash", "address": "bc1qspend"}}]
+        })
+    }
+
+    fn stub_block(block_hash: &str, verbosity: u64) -> Value {
+        let height = u64::from_str_radix(block_hash, 16).unwrap();
+        let coinbase = stub_coinbase(block_hash);
+        let spend = stub_spend();
+        let tx = if verbosity == 1 {
+            json!([coinbase["txid"], spend["txid"]])
+        } else {
+            json!([coinbase, spend])
+        };
+
+        json!({
+            "hash": block_hash,
+            "height": height,
+            "version": 536870912,
+            "time": 1_690_000_000 + height,
+            "mediantime": 1_690_000_000u64,
+            "nonce": 42,
+            "bits": "17053894",
+            "difficulty": 5.2e13,
+            "merkleroot": "ab".repeat(32),
+            "nTx": 2,
+            "size": 1000,
+            "weight": 4000,
+            "previousblockhash": stub_block_hash(height - 1),
+            "tx": tx
+        })
+    }
+
+    fn stub_result(method: &str, params: &Value) -> Value {
+        match method {
+            "getblockchaininfo" => json!({"chain": "main", "blocks": TIP_HEIGHT}),
+            "getblockhash" => json!(stub_block_hash(params[0].as_u64().unwrap())),
+            "getblock" => stub_block(params[0].as_str().unwrap(), params[1].as_u64().unwrap()),
+            "getrawtransaction" => stub_coinbase(params[2].as_str().unwrap()),
+            other => panic!("unexpected RPC method {}", other),
+        }
+    }
+
+    async fn read_rpc_request(socket: &mut TcpStream) -> Value {
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let read = socket.read(&mut chunk).await.unwrap();
+            assert!(read > 0, "connection closed before the request body");
+            buffer.extend_from_slice(&chunk[..read]);
+
+            let Some(headers_end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") else {
+                continue;
+            };
+            let content_length: usize = String::from_utf8_lossy(&buffer[..headers_end])
+                .to_lowercase()
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:").map(|value| value.trim().parse().unwrap()))
+                .unwrap_or(0);
+            let body_start = headers_end + 4;
+            if buffer.len() >= body_start + content_length {
+                return serde_json::from_slice(&buffer[body_start..body_start + content_length]).unwrap();
+            }
+        }
+    }
+
+    /// Поднимает заглушку bitcoind на случайном порту и возвращает её URL.
+    async fn spawn_stub_rpc() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let request = read_rpc_request(&mut socket).await;
+                    let method = request["method"].as_str().unwrap();
+                    let body = json!({
+                        "result": stub_result(method, &request["params"]),
+                        "error": null,
+                        "id": request["id"],
+                    }).to_string();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body,
+                    );
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+
+        format!("http://{}", address)
+    }
+
+    async fn stub_client() -> BitcoinCoreClient {
+        let config: BitcoinCoreRpcConfig = serde_json::from_value(json!({
+            "url": spawn_stub_rpc().await,
+            "latest_blocks_count": 2,
+        })).unwrap();
+        let client = Client::builder().no_proxy().build().unwrap();
+
+        BitcoinCoreClient::new(Arc::new(client), &config)
+    }
+
+    #[tokio::test]
+    async fn maps_latest_blocks_from_the_chain_tip() {
+        let client = stub_client().await;
+
+        let blocks = client.fetch_latest_blocks().await.unwrap();
+
+        let heights: Vec<u32> = blocks.iter().map(Block::get_height).collect();
+        assert_eq!(heights, vec![800_002, 800_001]);
+
+        let tip = &blocks[0];
+        assert_eq!(tip.get_id(), stub_block_hash(TIP_HEIGHT));
+        assert_eq!(tip.get_previous_block_hash(), stub_block_hash(TIP_HEIGHT - 1));
+        assert_eq!(tip.get_timestamp(), 1_690_000_000 + TIP_HEIGHT);
+        assert_eq!(tip.get_tx_count(), 2);
+        assert_eq!(tip.get_size(), 1000);
+        assert_eq!(tip.get_merkle_root(), "ab".repeat(32));
+        assert_eq!(tip.get_difficulty(), 5.2e13);
+    }
+
+    #[tokio::test]
+    async fn maps_full_block_transactions_with_coinbase_first() {
+        let client = stub_client().await;
+
+        let transactions = client.fetch_block_transactions(&stub_block_hash(TIP_HEIGHT)).await.unwrap();
+        assert_eq!(transactions.len(), 2);
+
+        let coinbase = &transactions[0];
+        assert!(coinbase.is_coinbase());
+        assert_eq!(coinbase.get_vin_scriptsig(), COINBASE_TAG_HEX);
+        assert_eq!(coinbase.get_fee(), Some(0));
+        assert_eq!(coinbase.get_main_reward_value(), Some(315_625_000));
+        assert_eq!(coinbase.get_main_reward_address(), Some(&Some("bc1qpool".to_string())));
+
+        let outputs = coinbase.get_vouts();
+        assert_eq!(outputs[0].get_scriptpubkey_type(), "v0_p2wpkh");
+        assert_eq!(outputs[1].get_scriptpubkey_type(), "op_return");
+        assert_eq!(outputs[1].get_value(), 0);
+        assert_eq!(outputs[1].get_scriptpubkey_address(), &None);
+
+        let spend = &transactions[1];
+        assert!(!spend.is_coinbase());
+        assert_eq!(spend.get_txid(), "ff".repeat(32));
+        assert_eq!(spend.get_fee(), Some(1410));
+        assert_eq!(spend.get_vsize(), 141);
+    }
+
+    #[tokio::test]
+    async fn fetches_coinbase_by_block_hash() {
+        let client = stub_client().await;
+        let block_hash = stub_block_hash(TIP_HEIGHT);
+
+        let coinbase = client.fetch_coinbase(&block_hash).await.unwrap();
+
+        assert_eq!(coinbase.get_txid(), format!("cb{}", &block_hash[2..]));
+        assert_eq!(coinbase.get_full_reward_value(), 315_625_000);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use crate::config::config::{ChainSourceKind, Config};
+use crate::domain::block::Block;
+use crate::domain::transaction::Transaction;
+use crate::infrastructure::collector::bitcoin_core::BitcoinCoreClient;
+use crate::infrastructure::collector::mempool::MempoolClient;
+
+/// Источник данных о блокчейне, из которого `BlockWatcher` берёт блоки и транзакции.
+#[async_trait]
+pub trait ChainSource: Send + Sync {
+    /// Имя backend'а для логов.
+    fn name(&self) -> &'static str;
+
+    /// Последние блоки сети, от самого нового к более старым.
+    async fn fetch_latest_blocks(&self) -> Result<Vec<Block>>;
+
+    /// Страница блоков, начиная с `height` и вниз по цепочке.
+    async fn fetch_blocks_from_height(&self, height: u64) -> Result<Vec<Block>>;
+
+    #[allow(dead_code)]
+    async fn fetch_block_by_hash(&self, block_hash: &str) -> Result<Block>;
+
+    #[allow(dead_code)]
+    async fn fetch_block_by_height(&self, height: u64) -> Result<Block>;
+
+    /// Coinbase-транзакция блока.
+    async fn fetch_coinbase(&self, block_hash: &str) -> Result<Transaction>;
+
+    /// Все транзакции блока, coinbase идёт первой.
+    async fn fetch_block_transactions(&self, block_hash: &str) -> Result<Vec<Transaction>>;
+}
+
+pub fn build_chain_source(config: &Config, client: Arc<Client>) -> Result<Arc<dyn ChainSource>> {
+    let chain_source: Arc<dyn ChainSource> = match config.get_chain_source() {
+        ChainSourceKind::Mempool => Arc::new(MempoolClient::new(client, config.get_api_url())),
+        ChainSourceKind::BitcoinCore => {
+            let rpc_config = config.get_bitcoin_core_rpc_config()
+                .ok_or_else(|| anyhow!("chain_source is bitcoin_core, but bitcoin_core_rpc config is missing"))?;
+
+            Arc::new(BitcoinCoreClient::new(client, rpc_config))
+        }
+    };
+
+    Ok(chain_source)
+}
\ No newline at end of file
@@ -1,56 +1,132 @@
 use std::sync::Arc;
 use anyhow::anyhow;
+use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::from_str;
 use crate::domain::block::Block;
 use crate::domain::transaction::Transaction;
+use crate::infrastructure::collector::chain_source::ChainSource;
 use crate::infrastructure::collector::namespace::NameSpaceApi;
 
-pub async fn fetch_latest_blocks(client: Arc<Client>, url: String) -> anyhow::Result<Vec<Block>> {
-    let ns = NameSpaceApi::Blocks(None).get_uri_by_ns();
-    let url = format!("{url}{ns}");
+/// Сколько транзакций mempool.space отдаёт на одной странице `block/{hash}/txs/{index}`.
+const BLOCK_TXS_PAGE_SIZE: u64 = 25;
 
-    let response = client.get(url).send().await?;
-    let body = response.text().await?;
+pub struct MempoolClient {
+    client: Arc<Client>,
+    url: String,
+}
 
-    let blocks_result: serde_json::Result<Vec<Block>> = from_str(&body);
+impl MempoolClient {
+    pub fn new(client: Arc<Client>, url: &str) -> Self {
+        Self {
+            client,
+            url: url.to_string(),
+        }
+    }
 
-    let blocks = if blocks_result.is_ok() {
-        blocks_result?
-    } else {
-        vec![]
-    };
+    async fn get_body(&self, ns: NameSpaceApi) -> anyhow::Result<String> {
+        let ns = ns.get_uri_by_ns();
+        let url = format!("{}{ns}", self.url);
 
-    Ok(blocks)
-}
+        let response = self.client.get(url).send().await?;
+        let body = response.text().await?;
 
-pub async fn fetch_get_coinbase_tx_id(client: Arc<Client>, url: String, hash: String) -> anyhow::Result<String> {
-    let ns = NameSpaceApi::BlockTxids(hash).get_uri_by_ns();
-    let url = format!("{url}{ns}");
+        Ok(body)
+    }
 
-    let response = client.get(url).send().await?;
-    let body = response.text().await?;
+    async fn fetch_coinbase_tx_id(&self, hash: String) -> anyhow::Result<String> {
+        let body = self.get_body(NameSpaceApi::BlockTxids(hash)).await?;
 
-    let txids: Vec<String> = from_str(&body)?;
+        let txids: Vec<String> = from_str(&body)?;
 
-    let coinbase_txid = txids[0].clone();
+        let coinbase_txid = txids.first()
+            .cloned()
+            .ok_or_else(|| anyhow!("block has no transactions"))?;
 
-    Ok(coinbase_txid)
+        Ok(coinbase_txid)
+    }
 }
 
-pub async fn fetch_get_coinbase(client: Arc<Client>, url: String, coinbase_txid: String) -> anyhow::Result<Transaction> {
-    let ns = NameSpaceApi::TxById(coinbase_txid).get_uri_by_ns();
-    let url = format!("{url}{ns}");
+#[async_trait]
+impl ChainSource for MempoolClient {
+    fn name(&self) -> &'static str {
+        "mempool"
+    }
+
+    async fn fetch_latest_blocks(&self) -> anyhow::Result<Vec<Block>> {
+        let body = self.get_body(NameSpaceApi::Blocks(None)).await?;
+
+        let blocks_result: serde_json::Result<Vec<Block>> = from_str(&body);
+
+        let blocks = if blocks_result.is_ok() {
+            blocks_result?
+        } else {
+            vec![]
+        };
+
+        Ok(blocks)
+    }
+
+    async fn fetch_blocks_from_height(&self, height: u64) -> anyhow::Result<Vec<Block>> {
+        let body = self.get_body(NameSpaceApi::Blocks(Some(height))).await?;
+
+        let blocks: Vec<Block> = from_str(&body)?;
+
+        Ok(blocks)
+    }
+
+    async fn fetch_block_by_hash(&self, block_hash: &str) -> anyhow::Result<Block> {
+        let body = self.get_body(NameSpaceApi::BlockByHash(block_hash.to_string())).await?;
+
+        let block: Block = from_str(&body)?;
+
+        Ok(block)
+    }
+
+    async fn fetch_block_by_height(&self, height: u64) -> anyhow::Result<Block> {
+        let block_hash = self.get_body(NameSpaceApi::BlockHeight(height)).await?;
+
+        self.fetch_block_by_hash(block_hash.trim()).await
+    }
+
+    async fn fetch_coinbase(&self, block_hash: &str) -> anyhow::Result<Transaction> {
+        let coinbase_txid = self.fetch_coinbase_tx_id(block_hash.to_string()).await?;
+
+        let body = self.get_body(NameSpaceApi::TxById(coinbase_txid)).await?;
+
+        let coinbase_tx: Transaction = from_str(&body)?;
+        let is_coinbase = coinbase_tx.get_vin_by_id(0)
+            .is_some_and(|vin| vin.is_coinbase);
+        if is_coinbase {
+            Ok(coinbase_tx)
+        } else {
+            Err(anyhow!("it isn't coinbase"))
+        }
+    }
+
+    async fn fetch_block_transactions(&self, block_hash: &str) -> anyhow::Result<Vec<Transaction>> {
+        let block = self.fetch_block_by_hash(block_hash).await?;
+        let tx_count = block.get_tx_count();
+
+        let mut transactions = Vec::with_capacity(tx_count as usize);
+        let mut start_index = 0;
+
+        while start_index < tx_count {
+            let body = self.get_body(NameSpaceApi::BlockTxs(block_hash.to_string(), Some(start_index))).await?;
+            let page: Vec<Transaction> = from_str(&body)?;
+
+            if page.is_empty() {
+                break;
+            }
+
+            transactions.extend(page);
+            start_index += BLOCK_TXS_PAGE_SIZE;
+        }
 
-    let response = client.get(url).send().await?;
-    let body = response.text().await?;
+        if transactions.len() as u64 != tx_count {
+            return Err(anyhow!("expected {} transactions in block {}, got {}", tx_count, block_hash, transactions.len()));
+        }
 
-    let coinbase_tx: Transaction = from_str(&body)?;
-    let vin = coinbase_tx.get_vin_by_id(0).unwrap();
-    let is_coinbase = vin.is_coinbase;
-    if is_coinbase {
-        Ok(coinbase_tx)
-    } else {
-        Err(anyhow!("it isn't coinbase"))
+        Ok(transactions)
     }
 }
\ No newline at end of file
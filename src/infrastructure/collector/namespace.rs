@@ -1,8 +1,9 @@
 pub enum NameSpaceApi {
     Blocks(Option<u64>),
-    _BlockByHash(String),
+    BlockByHash(String),
+    BlockHeight(u64),
     _BlockByHashCoinbase(String),
-    _BlockTxs(String),
+    BlockTxs(String, Option<u64>),
     BlockTxids(String),
     TxById(String),
 }
@@ -13,14 +14,17 @@ impl NameSpaceApi {
             NameSpaceApi::Blocks(from) => {
                 from.map_or("blocks/".to_string(), |height| format!("blocks/{height}"))
             }
-            NameSpaceApi::_BlockByHash(block_hash) => {
+            NameSpaceApi::BlockByHash(block_hash) => {
                 format!("block/{block_hash}")
             }
+            NameSpaceApi::BlockHeight(height) => {
+                format!("block-height/{height}")
+            }
             NameSpaceApi::_BlockByHashCoinbase(block_hash) => {
                 format!("block/{block_hash}/txs/0")
             }
-            NameSpaceApi::_BlockTxs(block_hash) => {
-                format!("block/{block_hash}/txs")
+            NameSpaceApi::BlockTxs(block_hash, start_index) => {
+                start_index.map_or(format!("block/{block_hash}/txs"), |index| format!("block/{block_hash}/txs/{index}"))
             }
             NameSpaceApi::BlockTxids(block_hash) => {
                 format!("block/{block_hash}/txids")
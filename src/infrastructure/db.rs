@@ -1,3 +1,6 @@
 pub mod postgres;
 pub mod models;
-pub mod repository;
\ No newline at end of file
+pub mod repository;
+pub mod consumer_lease;
+#[cfg(test)]
+pub mod test_support;
\ No newline at end of file
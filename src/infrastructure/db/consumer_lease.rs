@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::info;
+use sqlx::{Connection, PgConnection, PgPool};
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+use crate::utils::murmur3::murmur3_32;
+
+/// Как часто пробуем занять партицию, которую держит другой экземпляр.
+const ACQUIRE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// Как часто проверяем соединение, на котором держится lock.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Эмуляция single active consumer поверх advisory lock'а PostgreSQL: в rabbitmq-stream-client 0.4.4 его API нет.
+/// Партицию стрима читает только экземпляр, держащий lock.
+/// Lock сессионный и живёт на отдельном соединении вне пула, поэтому освобождается, когда экземпляр падает
+/// или теряет соединение с БД. Тогда партицию подхватывает другой экземпляр с сохранённого offset'а.
+pub struct ConsumerLease {
+    connection: PgConnection,
+    name: String,
+}
+
+impl ConsumerLease {
+    /// Ждёт lock на пару (consumer, stream). `None` — ожидание прервано остановкой сервиса.
+    pub async fn acquire(pool: &PgPool, consumer: &str, stream: &str, shutdown: &CancellationToken) -> Result<Option<Self>> {
+        let name = format!("{}@{}", consumer, stream);
+        let consumer_key = murmur3_32(consumer.as_bytes(), 0) as i32;
+        let stream_key = murmur3_32(stream.as_bytes(), 0) as i32;
+
+        let mut waiting = false;
+        loop {
+            let mut connection = pool.acquire().await?.detach();
+
+            let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1, $2)")
+                .bind(consumer_key)
+                .bind(stream_key)
+                .fetch_one(&mut connection)
+                .await?;
+
+            if locked {
+                info!("Consumer lease {} acquired", name);
+                return Ok(Some(Self { connection, name }));
+            }
+
+            connection.close().await?;
+            if !waiting {
+                info!("Consumer lease {} is held by another instance, waiting", name);
+                waiting = true;
+            }
+
+            tokio::select! {
+                _ = sleep(ACQUIRE_RETRY_INTERVAL) => {}
+                _ = shutdown.cancelled() => return Ok(None),
+            }
+        }
+    }
+
+    /// Завершается, только если соединение с lock'ом потеряно: lock мог перейти к другому экземпляру.
+    pub async fn lost(&mut self) -> anyhow::Error {
+        loop {
+            sleep(HEALTH_CHECK_INTERVAL).await;
+
+            if let Err(err) = self.connection.ping().await {
+                return anyhow!("Consumer lease {} lost: {}", self.name, err);
+            }
+        }
+    }
+
+    /// Закрытие соединения снимает lock.
+    pub async fn release(self) {
+        info!("Consumer lease {} released", self.name);
+        let _ = self.connection.close().await;
+    }
+}
\ No newline at end of file
@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 use sqlx::FromRow;
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct BlockModel {
     pub id: u32,
@@ -9,9 +10,11 @@ pub struct BlockModel {
     pub height: i64,
     pub timestamp: DateTime<Utc>,
     pub transaction_count: i32,
+    pub is_orphaned: bool,
     pub created_at: DateTime<Utc>
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Transaction {
     pub id: i32,
@@ -24,5 +27,66 @@ pub struct Transaction {
     pub main_reward: Option<i64>,
     pub miner_address: Option<String>,
     pub guessed_miner: Option<String>,
+    pub pool_name: Option<String>,
+    pub pool_slug: Option<String>,
+    pub pool_match_method: Option<String>,
+    pub is_orphaned: bool,
     pub created_at: DateTime<Utc>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct CoinbaseOutputModel {
+    pub id: i32,
+    pub block_hash: String,
+    pub vout_index: i32,
+    pub value: i64,
+    pub script_type: String,
+    pub address: Option<String>,
+    pub scriptpubkey_hex: String,
+    pub commitment_kind: Option<String>,
+    pub commitment_payload_hex: Option<String>,
+    pub created_at: DateTime<Utc>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct BlockMergedMiningModel {
+    pub id: i32,
+    pub block_hash: String,
+    pub marker: String,
+    pub aux_merkle_size: Option<i64>,
+    pub created_at: DateTime<Utc>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct BlockFeeStatsModel {
+    pub id: i32,
+    pub block_hash: String,
+    pub total_fees: i64,
+    pub total_vsize: i64,
+    pub transactions_count: i32,
+    pub min_feerate: f64,
+    pub max_feerate: f64,
+    pub median_feerate: f64,
+    pub p10_feerate: f64,
+    pub p25_feerate: f64,
+    pub p75_feerate: f64,
+    pub p90_feerate: f64,
+    pub created_at: DateTime<Utc>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct RewardAnomalyModel {
+    pub id: i32,
+    pub block_hash: String,
+    pub height: i64,
+    pub kind: String,
+    pub expected_subsidy: i64,
+    pub fees: Option<i64>,
+    pub coinbase_total: i64,
+    pub difference: i64,
+    pub detected_at: DateTime<Utc>
 }
\ No newline at end of file
@@ -8,23 +8,106 @@ use anyhow::Result;
 
 use chrono::Utc;
 
-use log::info;
-use tokio::sync::mpsc;
+use log::{error, info};
+use tokio::sync::{mpsc, oneshot};
 use tokio::sync::mpsc::Receiver;
 use tokio::time::{timeout, Duration as TokioDuration};
+use tokio_util::sync::CancellationToken;
 
-use crate::infrastructure::queue::queue_service::BlockAnalyticsMessage;
+use crate::infrastructure::queue::queue_service::{AnalyticsEvent, BlockAnalyticsMessage, ReorgEvent};
+use crate::utils::coinbase_commitment::CommitmentKind;
+
+/// Событие на запись в БД. В `ack` приходит результат записи: читатель стрима сохраняет offset,
+/// а watcher сдвигает вершину только после успешной записи.
+pub struct DbWrite {
+    pub event: AnalyticsEvent,
+    pub ack: oneshot::Sender<Result<()>>,
+}
+
+impl DbWrite {
+    pub fn with_ack(event: AnalyticsEvent) -> (Self, oneshot::Receiver<Result<()>>) {
+        let (ack, ack_receiver) = oneshot::channel();
+        (Self { event, ack }, ack_receiver)
+    }
+}
+
+/// Ошибка соединения или пула: запись стоит повторять, пока БД не вернётся.
+/// Остальные ошибки — отказ самой БД принять данные.
+pub fn is_transient_error(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<sqlx::Error>(),
+        Some(sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::Protocol(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed)
+    )
+}
+
+const DB_WRITE_RETRY_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const DB_WRITE_RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+pub enum WriteOutcome {
+    Saved,
+    /// БД отклонила событие `max_attempts` раз подряд.
+    Rejected(String),
+    /// Повторы прерваны остановкой сервиса.
+    Interrupted,
+}
+
+/// Повторяет запись, пока БД её не примет. Пока БД недоступна, повторы не ограничены, а отказ самой БД
+/// (ограничения, слишком длинные значения) после `max_attempts` попыток возвращается как `Rejected`.
+/// Ошибка означает, что writer БД остановлен или упал, не ответив. `subject` — что пишем, для логов.
+pub async fn write_until_saved(
+    db_sender: &mpsc::Sender<DbWrite>,
+    analytics_event: AnalyticsEvent,
+    subject: &str,
+    max_attempts: u32,
+    shutdown: &CancellationToken,
+) -> Result<WriteOutcome> {
+    let mut delay = DB_WRITE_RETRY_INITIAL_DELAY;
+    let mut rejections = 0;
+
+    loop {
+        let (db_write, ack) = DbWrite::with_ack(analytics_event.clone());
+        if db_sender.send(db_write).await.is_err() {
+            return Err(anyhow::anyhow!("Database writer is stopped, {} wasn't saved", subject));
+        }
+
+        match ack.await {
+            Ok(Ok(())) => return Ok(WriteOutcome::Saved),
+            Ok(Err(err)) if is_transient_error(&err) => {
+                error!("Couldn't save {}, retrying in {:?}: {}", subject, delay, err);
+            }
+            Ok(Err(err)) => {
+                rejections += 1;
+                if rejections >= max_attempts {
+                    return Ok(WriteOutcome::Rejected(format!("{:#}", err)));
+                }
+                error!("Database rejected {} ({}/{}), retrying in {:?}: {}", subject, rejections, max_attempts, delay, err);
+            }
+            Err(_) => return Err(anyhow::anyhow!("Database writer dropped {}", subject)),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.cancelled() => return Ok(WriteOutcome::Interrupted),
+        }
+        delay = (delay * 2).min(DB_WRITE_RETRY_MAX_DELAY);
+    }
+}
 
 pub struct Database {
     pool: Arc<Pool<Postgres>>,
-    pub sender: mpsc::Sender<BlockAnalyticsMessage>,
+    pub sender: mpsc::Sender<DbWrite>,
     // pub receiver: Receiver<BlockAnalyticsMessage>
 }
 
 impl Database {
-    pub async fn new(database_url: &str) -> Result<(Arc<Self>, Receiver<BlockAnalyticsMessage>)> {
+    pub async fn new(database_url: &str) -> Result<(Arc<Self>, Receiver<DbWrite>)> {
         info!("Connected with PostgreSQL");
-        let (sender, receiver) = mpsc::channel::<BlockAnalyticsMessage>(1000);
+        let (sender, receiver) = mpsc::channel::<DbWrite>(1000);
 
         let pool = PgPoolOptions::new()
             .max_connections(20)
@@ -59,15 +142,18 @@ impl Database {
     pub async fn save_block_and_coinbase(
         pool: Arc<PgPool>,
         message: &BlockAnalyticsMessage,
-    ) -> Result<(i32, i32)> {
+    ) -> Result<Option<(i32, i32)>> {
         let mut tx = pool.begin().await?;
 
         let ts = chrono::DateTime::from_timestamp(message.timestamp as i64, 0)
             .ok_or_else(|| anyhow::anyhow!("bad unix timestamp: {}", message.timestamp))?;
 
+        // Reorg рассылается во все партиции, поэтому партиция с новой веткой может применить его раньше,
+        // чем отстающая партиция дочитает осиротевший блок. Такой блок уже есть в `stale_blocks`:
+        // пишем его сразу orphaned, иначе он упрётся в `idx_blocks_active_height` и уйдёт в DLQ.
         let upsert_block_sql = r#"
-            INSERT INTO blocks (hash, height, "timestamp", transactions_count, created_at)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO blocks (hash, height, "timestamp", transactions_count, created_at, is_orphaned)
+            VALUES ($1, $2, $3, $4, $5, EXISTS (SELECT 1 FROM stale_blocks WHERE hash = $1))
             ON CONFLICT (hash) DO NOTHING
             RETURNING id
         "#;
@@ -78,21 +164,31 @@ impl Database {
             .bind(ts)
             .bind(message.transactions_count as i64)
             .bind(Utc::now())
-            .fetch_one(&mut *tx)
+            .fetch_optional(&mut *tx)
             .await?;
+
+        // Всё по блоку пишется одной транзакцией, поэтому если блок уже есть, есть и остальное.
+        // Повторная доставка из стрима (at-least-once) не должна считаться ошибкой.
+        let Some(block_row) = block_row else {
+            info!("Block {} (hash={}) is already saved, skipping", message.height, message.block_hash);
+            tx.rollback().await?;
+            return Ok(None);
+        };
         let block_id = block_row.get::<i32, _>("id");
         info!("Block upserted {} (hash={}) with id={}", message.height, message.block_hash, block_id);
 
         let coinbase = &message.coinbase_info;
-        let txid = format!("coinbase_{}", message.block_hash);
+        // `transactions.txid` — VARCHAR(64). Для старых сообщений без txid ключом служит хеш блока: он тоже уникален.
+        let txid = coinbase.txid.clone().unwrap_or_else(|| message.block_hash.clone());
 
         let upsert_tx_sql = r#"
             INSERT INTO transactions (
                 txid, block_hash, fee, size, is_coinbase,
-                main_reward, miner_address, full_reward, guessed_miner, created_at
+                main_reward, miner_address, full_reward, guessed_miner,
+                pool_name, pool_slug, pool_match_method, created_at, is_orphaned
             )
-            VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10)
-            ON CONFLICT (txid) DO NOTHING
+            VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13, EXISTS (SELECT 1 FROM stale_blocks WHERE hash = $2))
+            ON CONFLICT (txid, block_hash) DO NOTHING
             RETURNING id
         "#;
 
@@ -106,26 +202,330 @@ impl Database {
             .bind(coinbase.miner_address.clone())
             .bind(coinbase.full_reward)
             .bind(coinbase.guessed_miner.clone())
+            .bind(coinbase.pool_name.clone())
+            .bind(coinbase.pool_slug.clone())
+            .bind(coinbase.pool_match_method.clone())
             .bind(Utc::now())
-            .fetch_one(&mut *tx)
+            .fetch_optional(&mut *tx)
             .await?;
 
-
-        let transaction_id = tx_row.get::<i32, _>("id");
+        // Строка с тем же (txid, block_hash) уже есть: берём её id, а не падаем на пустом RETURNING.
+        let transaction_id = match tx_row {
+            Some(tx_row) => tx_row.get::<i32, _>("id"),
+            None => sqlx::query_scalar::<_, i32>("SELECT id FROM transactions WHERE txid = $1 AND block_hash = $2")
+                .bind(&txid)
+                .bind(&message.block_hash)
+                .fetch_one(&mut *tx)
+                .await?,
+        };
         info!(
             "Coinbase upserted for block hash={} with txid={} id={}",
             message.block_hash, txid, transaction_id
         );
 
+        let insert_output_sql = r#"
+            INSERT INTO coinbase_outputs (
+                block_hash, vout_index, value, script_type, address, scriptpubkey_hex,
+                commitment_kind, commitment_payload_hex, created_at
+            )
+            VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9)
+            ON CONFLICT (block_hash, vout_index) DO NOTHING
+        "#;
+
+        for output in coinbase.outputs.iter() {
+            let commitment = coinbase.commitments.iter()
+                .find(|commitment| commitment.vout_index == output.vout_index);
+
+            sqlx::query(insert_output_sql)
+                .bind(&message.block_hash)
+                .bind(output.vout_index as i32)
+                .bind(output.value)
+                .bind(&output.script_type)
+                .bind(output.address.clone())
+                .bind(&output.scriptpubkey)
+                .bind(commitment.map(|commitment| commitment.kind.as_str()))
+                .bind(commitment.map(|commitment| commitment.payload_hex.clone()))
+                .bind(Utc::now())
+                .execute(&mut *tx)
+                .await?;
+        }
+        info!("{} coinbase outputs saved for block hash={}", coinbase.outputs.len(), message.block_hash);
+
+        let insert_merged_mining_sql = r#"
+            INSERT INTO block_merged_mining (block_hash, marker, aux_merkle_size, created_at)
+            VALUES ($1,$2,$3,$4)
+            ON CONFLICT (block_hash, marker) DO NOTHING
+        "#;
+
+        for marker in coinbase.merged_mining.iter() {
+            let aux_merkle_size = match marker {
+                CommitmentKind::AuxPow => coinbase.aux_pow.as_ref().map(|header| header.merkle_size as i64),
+                _ => None,
+            };
+
+            sqlx::query(insert_merged_mining_sql)
+                .bind(&message.block_hash)
+                .bind(marker.as_str())
+                .bind(aux_merkle_size)
+                .bind(Utc::now())
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        if let Some(fee_stats) = &message.fee_stats {
+            let insert_fee_stats_sql = r#"
+                INSERT INTO block_fee_stats (
+                    block_hash, total_fees, total_vsize, transactions_count,
+                    min_feerate, max_feerate, median_feerate,
+                    p10_feerate, p25_feerate, p75_feerate, p90_feerate, created_at
+                )
+                VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12)
+                ON CONFLICT (block_hash) DO NOTHING
+            "#;
+
+            sqlx::query(insert_fee_stats_sql)
+                .bind(&message.block_hash)
+                .bind(fee_stats.total_fees)
+                .bind(fee_stats.total_vsize as i64)
+                .bind(fee_stats.transactions_count as i32)
+                .bind(fee_stats.min_feerate)
+                .bind(fee_stats.max_feerate)
+                .bind(fee_stats.median_feerate)
+                .bind(fee_stats.p10_feerate)
+                .bind(fee_stats.p25_feerate)
+                .bind(fee_stats.p75_feerate)
+                .bind(fee_stats.p90_feerate)
+                .bind(Utc::now())
+                .execute(&mut *tx)
+                .await?;
+            info!("Fee stats saved for block hash={}", message.block_hash);
+        }
+
+        if let Some(anomaly) = &message.reward_anomaly {
+            let insert_anomaly_sql = r#"
+                INSERT INTO reward_anomalies (
+                    block_hash, height, kind, expected_subsidy, fees, coinbase_total, difference, detected_at
+                )
+                VALUES ($1,$2,$3,$4,$5,$6,$7,$8)
+                ON CONFLICT (block_hash) DO NOTHING
+            "#;
+
+            sqlx::query(insert_anomaly_sql)
+                .bind(&message.block_hash)
+                .bind(anomaly.height as i64)
+                .bind(anomaly.kind.as_str())
+                .bind(anomaly.expected_subsidy)
+                .bind(anomaly.fees)
+                .bind(anomaly.coinbase_total)
+                .bind(anomaly.difference)
+                .bind(Utc::now())
+                .execute(&mut *tx)
+                .await?;
+            info!("Reward anomaly saved for block hash={}", message.block_hash);
+        }
+
         timeout(TokioDuration::from_secs(3), tx.commit()).await??;
 
-        Ok((block_id, transaction_id))
+        Ok(Some((block_id, transaction_id)))
     }
 
-    pub async fn queue_messages_reader(mut receiver: Receiver<BlockAnalyticsMessage>, pool: Arc<PgPool>) {
-        while let Some(message) = receiver.recv().await {
+    /// Помечает вытесненную ветку как orphaned и записывает её в `stale_blocks`.
+    /// Блоки новой ветки могли быть orphaned прошлым reorg'ом (A→B→A): они снова становятся активными
+    /// и убираются из `stale_blocks`, иначе высота осталась бы без активного блока.
+    pub async fn save_reorg(pool: Arc<PgPool>, event: &ReorgEvent) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
+        let orphaned_hashes: Vec<String> = event.orphaned_blocks.iter()
+            .map(|block| block.hash.clone())
+            .collect();
+
+        sqlx::query("UPDATE blocks SET is_orphaned = TRUE WHERE hash = ANY($1)")
+            .bind(&orphaned_hashes)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("UPDATE transactions SET is_orphaned = TRUE WHERE block_hash = ANY($1)")
+            .bind(&orphaned_hashes)
+            .execute(&mut *tx)
+            .await?;
+
+        // Сначала снимаем активность со старой ветки: `idx_blocks_active_height` не допускает двух активных блоков на высоте.
+        let new_hashes: Vec<String> = event.new_blocks.iter()
+            .map(|block| block.hash.clone())
+            .collect();
+
+        sqlx::query("UPDATE blocks SET is_orphaned = FALSE WHERE hash = ANY($1)")
+            .bind(&new_hashes)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("UPDATE transactions SET is_orphaned = FALSE WHERE block_hash = ANY($1)")
+            .bind(&new_hashes)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM stale_blocks WHERE hash = ANY($1)")
+            .bind(&new_hashes)
+            .execute(&mut *tx)
+            .await?;
+
+        let insert_stale_sql = r#"
+            INSERT INTO stale_blocks (
+                hash, height, guessed_miner, pool_slug, replaced_by_hash, replaced_by_miner,
+                replaced_by_pool_slug, fork_height, fork_hash, reorg_depth, detected_at
+            )
+            VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11)
+            ON CONFLICT (hash) DO NOTHING
+        "#;
+
+        let detected_at = chrono::DateTime::from_timestamp(event.detected_at as i64, 0)
+            .unwrap_or_else(Utc::now);
+
+        for orphaned in event.orphaned_blocks.iter() {
+            let replaced_by = event.new_blocks.iter()
+                .find(|block| block.height == orphaned.height);
+
+            sqlx::query(insert_stale_sql)
+                .bind(&orphaned.hash)
+                .bind(orphaned.height as i64)
+                .bind(orphaned.guessed_miner.clone())
+                .bind(orphaned.pool_slug.clone())
+                .bind(replaced_by.map(|block| block.hash.clone()))
+                .bind(replaced_by.and_then(|block| block.guessed_miner.clone()))
+                .bind(replaced_by.and_then(|block| block.pool_slug.clone()))
+                .bind(event.fork_height as i64)
+                .bind(&event.fork_hash)
+                .bind(event.depth() as i32)
+                .bind(detected_at)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        timeout(TokioDuration::from_secs(3), tx.commit()).await??;
+
+        info!("Reorg at height {} saved: {} blocks orphaned", event.fork_height, event.depth());
+
+        Ok(())
+    }
+
+    /// Receiver передаётся по ссылке: после перезапуска супервизором writer продолжает читать тот же канал.
+    /// По `shutdown` канал закрывается, а уже отправленные записи дописываются.
+    pub async fn queue_messages_reader(receiver: &mut Receiver<DbWrite>, pool: Arc<PgPool>, shutdown: CancellationToken) {
+        loop {
+            let db_write = tokio::select! {
+                db_write = receiver.recv() => db_write,
+                _ = shutdown.cancelled(), if !receiver.is_closed() => {
+                    info!("Database writer is shutting down, {} writes left in the channel", receiver.len());
+                    receiver.close();
+                    continue;
+                }
+            };
+            let Some(DbWrite { event, ack }) = db_write else {
+                break;
+            };
+
             let pool = Arc::clone(&pool);
-            let _ = Database::save_block_and_coinbase(pool, &message).await;
+            let result = match event {
+                AnalyticsEvent::Block(message) => {
+                    Database::save_block_and_coinbase(pool, &message).await.map(|_| ())
+                }
+                AnalyticsEvent::Reorg(reorg_event) => {
+                    Database::save_reorg(pool, &reorg_event).await
+                }
+            };
+
+            if let Err(e) = &result {
+                error!("Error saving analytics event: {:?}", e);
+            }
+
+            let _ = ack.send(result);
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::block::BlockRef;
+    use crate::infrastructure::db::test_support::{block_message, TestDatabase};
+
+    #[tokio::test]
+    async fn saves_duplicate_coinbase_txid_in_another_block() {
+        let Some(database) = TestDatabase::connect().await else {
+            return;
+        };
+        // Блоки 91812 и 91842 содержат coinbase с одним и тем же txid.
+        let coinbase_txid = "d5d27987d2a3dfc724e359870c6644b40e497bdc0589a033220fe15429d88599";
+        let first = block_message(91_812, &format!("{:064x}", 91_812), coinbase_txid, "unknown");
+        let second = block_message(91_842, &format!("{:064x}", 91_842), coinbase_txid, "unknown");
+
+        let first_ids = Database::save_block_and_coinbase(Arc::clone(&database.pool), &first).await.unwrap();
+        let second_ids = Database::save_block_and_coinbase(Arc::clone(&database.pool), &second).await.unwrap();
+
+        let (_, first_tx_id) = first_ids.unwrap();
+        let (_, second_tx_id) = second_ids.unwrap();
+        assert_ne!(first_tx_id, second_tx_id);
+
+        let coinbase_blocks: Vec<String> = sqlx::query_scalar("SELECT block_hash FROM transactions WHERE txid = $1 ORDER BY block_hash")
+            .bind(coinbase_txid)
+            .fetch_all(&*database.pool)
+            .await
+            .unwrap();
+        assert_eq!(coinbase_blocks, vec![first.block_hash.clone(), second.block_hash.clone()]);
+
+        // Повторная доставка того же блока ничего не пишет.
+        let redelivered = Database::save_block_and_coinbase(Arc::clone(&database.pool), &second).await.unwrap();
+        assert_eq!(redelivered, None);
+
+        database.drop_schema().await;
+    }
+    #[tokio::test]
+    async fn reorg_back_to_an_orphaned_block_makes_it_active_again() {
+        let Some(database) = TestDatabase::connect().await else {
+            return;
+        };
+        let first = block_message(800_001, &format!("{:064x}", 0xa1), &format!("{:064x}", 0xa2), "foundry");
+        let second = block_message(800_001, &format!("{:064x}", 0xb1), &format!("{:064x}", 0xb2), "viabtc");
+        let block_ref = |message: &BlockAnalyticsMessage| BlockRef {
+            height: message.height as u64,
+            hash: message.block_hash.clone(),
+            guessed_miner: Some(message.coinbase_info.guessed_miner.clone()),
+            pool_slug: message.coinbase_info.pool_slug.clone(),
+        };
+        let reorg = |orphaned: &BlockAnalyticsMessage, replacement: &BlockAnalyticsMessage| ReorgEvent {
+            fork_height: 800_000,
+            fork_hash: format!("{:064x}", 800_000),
+            orphaned_blocks: vec![block_ref(orphaned)],
+            new_blocks: vec![block_ref(replacement)],
+            detected_at: 1_700_000_000,
+        };
+
+        // A, reorg на B, затем reorg обратно на A.
+        Database::save_block_and_coinbase(Arc::clone(&database.pool), &first).await.unwrap();
+        Database::save_reorg(Arc::clone(&database.pool), &reorg(&first, &second)).await.unwrap();
+        Database::save_block_and_coinbase(Arc::clone(&database.pool), &second).await.unwrap();
+        Database::save_reorg(Arc::clone(&database.pool), &reorg(&second, &first)).await.unwrap();
+        // Повторная доставка блока A ничего не меняет.
+        Database::save_block_and_coinbase(Arc::clone(&database.pool), &first).await.unwrap();
+
+        let blocks: Vec<(String, bool)> = sqlx::query_as("SELECT hash, is_orphaned FROM blocks WHERE height = 800001 ORDER BY hash")
+            .fetch_all(&*database.pool)
+            .await
+            .unwrap();
+        assert_eq!(blocks, vec![(first.block_hash.clone(), false), (second.block_hash.clone(), true)]);
+
+        let coinbases: Vec<(String, bool)> = sqlx::query_as("SELECT block_hash, is_orphaned FROM transactions ORDER BY block_hash")
+            .fetch_all(&*database.pool)
+            .await
+            .unwrap();
+        assert_eq!(coinbases, vec![(first.block_hash.clone(), false), (second.block_hash.clone(), true)]);
+
+        let stale: Vec<(String, Option<String>)> = sqlx::query_as("SELECT hash, replaced_by_hash FROM stale_blocks")
+            .fetch_all(&*database.pool)
+            .await
+            .unwrap();
+        assert_eq!(stale, vec![(second.block_hash.clone(), Some(first.block_hash.clone()))]);
+
+        database.drop_schema().await;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Executor, PgPool};
+
+use crate::infrastructure::queue::queue_service::BlockAnalyticsMessage;
+
+static SCHEMA_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Отдельная схема с применёнными миграциями на каждый тест, чтобы тесты не мешали друг другу.
+/// Тесты с БД работают, только если задан `TEST_DATABASE_URL`, иначе пропускаются.
+pub struct TestDatabase {
+    pub pool: Arc<PgPool>,
+    admin_pool: PgPool,
+    schema: String,
+}
+
+impl TestDatabase {
+    pub async fn connect() -> Option<Self> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL is not set, skipping PostgreSQL test");
+            return None;
+        };
+
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+        let schema = format!("test_{}_{}_{}", std::process::id(), nanos, SCHEMA_COUNTER.fetch_add(1, Ordering::Relaxed));
+
+        let admin_pool = PgPool::connect(&database_url).await.unwrap();
+        admin_pool.execute(format!("CREATE SCHEMA {}", schema).as_str()).await.unwrap();
+
+        let search_path = format!("SET search_path TO {}", schema);
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .after_connect(move |connection, _| {
+                let search_path = search_path.clone();
+                Box::pin(async move {
+                    connection.execute(search_path.as_str()).await?;
+                    Ok(())
+                })
+            })
+            .connect(&database_url)
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        Some(Self { pool: Arc::new(pool), admin_pool, schema })
+    }
+
+    pub async fn drop_schema(self) {
+        self.pool.close().await;
+        self.admin_pool.execute(format!("DROP SCHEMA {} CASCADE", self.schema).as_str()).await.unwrap();
+    }
+}
+
+/// Минимальное сообщение о блоке с заданной coinbase и пулом.
+pub fn block_message(height: u32, block_hash: &str, coinbase_txid: &str, pool_slug: &str) -> BlockAnalyticsMessage {
+    serde_json::from_value(json!({
+        "height": height,
+        "block_hash": block_hash,
+        "timestamp": 1_690_000_000u64 + height as u64,
+        "size": 1000,
+        "merkle_root": "ab".repeat(32),
+        "difficulty": 1.0,
+        "transactions_count": 1,
+        "coinbase_info": {
+            "txid": coinbase_txid,
+            "main_reward": 5_000_000_000i64,
+            "miner_address": null,
+            "full_reward": 5_000_000_000i64,
+            "fee": 0,
+            "guessed_miner": pool_slug,
+            "pool_name": pool_slug,
+            "pool_slug": pool_slug,
+            "pool_match_method": "coinbase_tag",
+            "rewards_and_addresses": []
+        }
+    })).unwrap()
+}
\ No newline at end of file
@@ -1,2 +1,10 @@
 pub mod stream_rabbitmq;
-pub mod queue_service;
\ No newline at end of file
+pub mod queue_service;
+pub mod spool;
+pub mod publishing;
+pub mod message_bus;
+pub mod in_memory;
+pub mod envelope;
+pub mod codec;
+pub mod dead_letter;
+pub mod partitioning;
\ No newline at end of file
@@ -0,0 +1,126 @@
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+pub const CBOR_CONTENT_TYPE: &str = "application/cbor";
+pub const MESSAGE_PACK_CONTENT_TYPE: &str = "application/msgpack";
+
+/// Формат тела сообщения в стриме. Записывается в content-type сообщения,
+/// поэтому producer'ы с разными кодеками могут писать в один стрим.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    #[default]
+    Json,
+    Cbor,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+}
+
+impl Codec {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Codec::Json => JSON_CONTENT_TYPE,
+            Codec::Cbor => CBOR_CONTENT_TYPE,
+            Codec::MessagePack => MESSAGE_PACK_CONTENT_TYPE,
+        }
+    }
+
+    /// Сообщения без content-type публиковались до появления кодеков и всегда были JSON.
+    pub fn from_content_type(content_type: Option<&str>) -> Result<Self> {
+        match content_type {
+            None | Some(JSON_CONTENT_TYPE) => Ok(Codec::Json),
+            Some(CBOR_CONTENT_TYPE) => Ok(Codec::Cbor),
+            Some(MESSAGE_PACK_CONTENT_TYPE) => Ok(Codec::MessagePack),
+            Some(other) => Err(anyhow!("unsupported content type {}", other)),
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        let body = match self {
+            Codec::Json => serde_json::to_vec(value)?,
+            Codec::Cbor => {
+                let mut body = Vec::new();
+                ciborium::into_writer(value, &mut body)?;
+                body
+            }
+            // Структуры пишем map'ами с именами полей: иначе `#[serde(default)]` и upcasting не работают.
+            Codec::MessagePack => rmp_serde::to_vec_named(value)?,
+        };
+
+        Ok(body)
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, body: &[u8]) -> Result<T> {
+        let value = match self {
+            Codec::Json => serde_json::from_slice(body)?,
+            Codec::Cbor => ciborium::from_reader(body)?,
+            Codec::MessagePack => rmp_serde::from_slice(body)?,
+        };
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::domain::block::BlockRef;
+    use crate::infrastructure::db::test_support::block_message;
+    use crate::infrastructure::queue::envelope::{Envelope, MessageType, CURRENT_SCHEMA_VERSION};
+    use crate::infrastructure::queue::queue_service::{AnalyticsEvent, ReorgEvent};
+
+    fn events() -> Vec<AnalyticsEvent> {
+        let mut block = block_message(800_000, &"aa".repeat(32), &"bb".repeat(32), "foundry");
+        block.difficulty = 53_911_173_001_054.59;
+        let reorg = ReorgEvent {
+            fork_height: 800_000,
+            fork_hash: "aa".repeat(32),
+            orphaned_blocks: vec![BlockRef { height: 800_001, hash: "cc".repeat(32), guessed_miner: None, pool_slug: None }],
+            new_blocks: vec![BlockRef {
+                height: 800_001,
+                hash: "dd".repeat(32),
+                guessed_miner: Some("/Foundry USA Pool/".to_string()),
+                pool_slug: Some("foundryusa".to_string()),
+            }],
+            detected_at: 1_700_000_000,
+        };
+
+        vec![AnalyticsEvent::Block(block), AnalyticsEvent::Reorg(reorg)]
+    }
+
+    #[test]
+    fn round_trips_envelopes_through_every_codec() {
+        for codec in [Codec::Json, Codec::Cbor, Codec::MessagePack] {
+            let decoded_codec = Codec::from_content_type(Some(codec.content_type())).unwrap();
+            assert_eq!(decoded_codec, codec);
+
+            for event in events() {
+                let body = codec.encode(&Envelope::wrap(&event, "mining-analytics-producer".to_string(), 1_700_000_001)).unwrap();
+
+                let envelope = Envelope::decode(decoded_codec, &body).unwrap();
+                assert_eq!(envelope.schema_version, CURRENT_SCHEMA_VERSION);
+                assert_eq!(envelope.producer_id, "mining-analytics-producer");
+                assert_eq!(envelope.produced_at, 1_700_000_001);
+                let expected_type = match event {
+                    AnalyticsEvent::Block(_) => MessageType::BlockAnalytics,
+                    AnalyticsEvent::Reorg(_) => MessageType::Reorg,
+                };
+                assert_eq!(envelope.message_type, expected_type);
+
+                let decoded = envelope.into_event().unwrap();
+                assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&event).unwrap(), "{:?}", codec);
+            }
+        }
+    }
+
+    #[test]
+    fn messages_without_content_type_are_json() {
+        assert_eq!(Codec::from_content_type(None).unwrap(), Codec::Json);
+        assert!(Codec::from_content_type(Some("application/x-protobuf")).is_err());
+        assert!(Codec::Cbor.decode::<Value>(b"{\"height\": 1}").is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use log::{info, warn};
+use serde_json::Value;
+
+use crate::config::config::StreamsConfig;
+use crate::infrastructure::queue::codec::Codec;
+use crate::infrastructure::queue::message_bus::{Delivery, MessageBus, OutgoingMessage, Subscription};
+
+const REASON_HEADER: &str = "x-dlq-reason";
+const STAGE_HEADER: &str = "x-dlq-stage";
+const SOURCE_STREAM_HEADER: &str = "x-dlq-source-stream";
+const SOURCE_OFFSET_HEADER: &str = "x-dlq-source-offset";
+const FAILED_AT_HEADER: &str = "x-dlq-failed-at";
+
+/// Inspector никогда не сохраняет offset, поэтому каждый запуск читает DLQ с начала.
+const INSPECTOR_SUFFIX: &str = "inspector";
+/// Replayer сохраняет offset после каждого сообщения: повторный replay не отправит их снова.
+const REPLAYER_SUFFIX: &str = "replayer";
+/// Producer, которым replay пишет в исходный стрим: `{source_stream}-dlq-replayer`.
+const REPLAY_PRODUCER_SUFFIX: &str = "dlq-replayer";
+
+/// Стрим не сообщает, что сообщения кончились: считаем DLQ прочитанным, если новых нет столько времени.
+const READ_IDLE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// На каком шаге сообщение не удалось обработать.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadLetterStage {
+    /// Не разобрано: неизвестный content-type, версия схемы или битое тело.
+    Decode,
+    /// Разобрано, но БД его не принимает.
+    Persist,
+}
+
+impl DeadLetterStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeadLetterStage::Decode => "decode",
+            DeadLetterStage::Persist => "persist",
+        }
+    }
+}
+
+/// DLQ стрима аналитики (`mining-analytics-dlq`): сообщения, которые читатель не смог разобрать или записать.
+/// Тело и content-type сохраняются как есть, причина и исходный offset — в заголовках.
+pub struct DeadLetterQueue {
+    message_bus: Arc<dyn MessageBus>,
+    source_stream: String,
+    stream: String,
+}
+
+impl DeadLetterQueue {
+    pub fn new(message_bus: Arc<dyn MessageBus>, streams: &StreamsConfig) -> Self {
+        Self {
+            message_bus,
+            source_stream: streams.get_analytics().to_string(),
+            stream: streams.get_dead_letter(),
+        }
+    }
+
+    /// Стрим, из которого пришли сообщения, например партиция аналитики. Replay вернёт их туда же.
+    pub fn with_source_stream(mut self, source_stream: &str) -> Self {
+        self.source_stream = source_stream.to_string();
+        self
+    }
+
+    pub async fn send(&self, delivery: &Delivery, stage: DeadLetterStage, reason: &str) -> Result<()> {
+        let source_stream = &self.source_stream;
+        let mut message = OutgoingMessage::new(delivery.body.clone());
+        message.content_type = delivery.content_type.clone();
+        message.headers = BTreeMap::from([
+            (REASON_HEADER.to_string(), reason.to_string()),
+            (STAGE_HEADER.to_string(), stage.as_str().to_string()),
+            (SOURCE_STREAM_HEADER.to_string(), source_stream.to_string()),
+            (SOURCE_OFFSET_HEADER.to_string(), delivery.offset.to_string()),
+            (FAILED_AT_HEADER.to_string(), Utc::now().timestamp().to_string()),
+        ]);
+
+        self.message_bus.publish(&self.stream, vec![message]).await
+            .with_context(|| format!("Couldn't move {} offset {} to {}", source_stream, delivery.offset, self.stream))?;
+        warn!("Message {} at offset {} moved to {} ({}): {}", source_stream, delivery.offset, self.stream, stage.as_str(), reason);
+
+        Ok(())
+    }
+
+    /// Печатает первые `limit` сообщений DLQ, ничего не меняя. Возвращает число напечатанных.
+    pub async fn inspect(&self, limit: usize) -> Result<usize> {
+        let mut subscription = self.message_bus.subscribe(&self.stream, &self.subscriber_name(INSPECTOR_SUFFIX)).await?;
+
+        let mut printed = 0;
+        while printed < limit {
+            let Some(delivery) = Self::next_before_idle(&mut subscription).await? else {
+                break;
+            };
+            println!("{}", Self::describe(&delivery));
+            printed += 1;
+        }
+
+        subscription.close().await;
+        Ok(printed)
+    }
+
+    /// Отправляет ещё не переотправленные сообщения DLQ обратно в исходный стрим. Возвращает их число.
+    /// Если сообщение снова не обработается, читатель положит его в DLQ ещё раз, уже с новой причиной.
+    /// У replay свой producer, а publishing id — offset сообщения в DLQ: id растут, не пересекаются с id live-producer'а,
+    /// и сообщение, отправленное перед сбоем, но не подтверждённое в DLQ, повторно отбрасывается брокером.
+    pub async fn replay(&self) -> Result<usize> {
+        let mut subscription = self.message_bus.subscribe(&self.stream, &self.subscriber_name(REPLAYER_SUFFIX)).await?;
+
+        let mut replayed = 0;
+        while let Some(delivery) = Self::next_before_idle(&mut subscription).await? {
+            let source_stream = delivery.headers.get(SOURCE_STREAM_HEADER)
+                .map_or(self.source_stream.as_str(), String::as_str);
+
+            let message = OutgoingMessage {
+                producer: Some(format!("{}-{}", source_stream, REPLAY_PRODUCER_SUFFIX)),
+                publishing_id: Some(delivery.offset),
+                content_type: delivery.content_type.clone(),
+                ..OutgoingMessage::new(delivery.body.clone())
+            };
+            self.message_bus.publish(source_stream, vec![message]).await?;
+            subscription.commit(delivery.offset).await?;
+
+            info!("Replayed {} offset {} to {}", self.stream, delivery.offset, source_stream);
+            replayed += 1;
+        }
+
+        subscription.close().await;
+        Ok(replayed)
+    }
+
+    fn subscriber_name(&self, suffix: &str) -> String {
+        format!("{}-{}", self.stream, suffix)
+    }
+
+    async fn next_before_idle(subscription: &mut Box<dyn Subscription>) -> Result<Option<Delivery>> {
+        match tokio::time::timeout(READ_IDLE_TIMEOUT, subscription.next()).await {
+            Ok(delivery) => delivery,
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn describe(delivery: &Delivery) -> String {
+        let header = |name: &str| delivery.headers.get(name).map_or("-", String::as_str);
+
+        let body = Codec::from_content_type(delivery.content_type.as_deref())
+            .and_then(|codec| codec.decode::<Value>(&delivery.body))
+            .map(|value| value.to_string())
+            .unwrap_or_else(|_| format!("<{} bytes, not decodable>", delivery.body.len()));
+
+        format!(
+            "offset={} source={}@{} stage={} failed_at={} content_type={} reason={}\n  {}",
+            delivery.offset,
+            header(SOURCE_STREAM_HEADER),
+            header(SOURCE_OFFSET_HEADER),
+            header(STAGE_HEADER),
+            header(FAILED_AT_HEADER),
+            delivery.content_type.as_deref().unwrap_or("-"),
+            header(REASON_HEADER),
+            body,
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::queue::in_memory::InMemoryBus;
+
+    #[tokio::test]
+    async fn replays_into_a_stream_with_live_publishing_ids() {
+        let message_bus: Arc<dyn MessageBus> = Arc::new(InMemoryBus::new());
+        let streams = StreamsConfig::default();
+        let live_message = |height: u64| OutgoingMessage {
+            producer: Some(streams.get_producer_name()),
+            publishing_id: Some(height << 1),
+            ..OutgoingMessage::new(format!("block {}", height).into_bytes())
+        };
+        message_bus.publish("mining-analytics", vec![live_message(800_000)]).await.unwrap();
+
+        let dead_letters = DeadLetterQueue::new(Arc::clone(&message_bus), &streams);
+        for (offset, body) in [(3, "first"), (7, "second")] {
+            let delivery = Delivery { offset, body: body.as_bytes().to_vec(), content_type: None, headers: BTreeMap::new() };
+            dead_letters.send(&delivery, DeadLetterStage::Persist, "rejected").await.unwrap();
+        }
+
+        assert_eq!(dead_letters.replay().await.unwrap(), 2);
+        message_bus.publish("mining-analytics", vec![live_message(800_001)]).await.unwrap();
+
+        // Ни replay, ни следующий live-блок не отброшены как дубликаты.
+        let mut subscription = message_bus.subscribe("mining-analytics", "test").await.unwrap();
+        let mut bodies = Vec::new();
+        while let Ok(delivery) = tokio::time::timeout(Duration::from_millis(50), subscription.next()).await {
+            bodies.push(String::from_utf8(delivery.unwrap().unwrap().body).unwrap());
+        }
+        assert_eq!(bodies, ["block 800000", "first", "second", "block 800001"]);
+    }
+}
\ No newline at end of file
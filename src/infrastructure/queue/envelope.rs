@@ -0,0 +1,175 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::infrastructure::queue::codec::Codec;
+use crate::infrastructure::queue::queue_service::AnalyticsEvent;
+
+/// Версия, которую пишет этот сервис.
+/// 1 — голый JSON `BlockAnalyticsMessage`/`ReorgEvent` без конверта; 2 — конверт.
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// Приведение payload'а версии `n` к версии `n + 1`, индекс — `n - 1`.
+/// Меняя формат payload'а, увеличиваем `CURRENT_SCHEMA_VERSION` и добавляем сюда шаг,
+/// чтобы старые сегменты стрима оставались читаемыми.
+const UPCASTERS: [fn(&mut Value); CURRENT_SCHEMA_VERSION as usize - 1] = [
+    // 1 -> 2: payload не изменился, появился только конверт.
+    |_| {},
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageType {
+    BlockAnalytics,
+    Reorg,
+}
+
+/// Сообщение стрима `mining-analytics`: метаданные и payload.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub schema_version: u32,
+    pub message_type: MessageType,
+    /// Producer, опубликовавший сообщение.
+    pub producer_id: String,
+    /// Когда событие поставлено в очередь на отправку, unix-время в секундах.
+    pub produced_at: u64,
+    pub payload: T,
+}
+
+impl<'a> Envelope<&'a AnalyticsEvent> {
+    pub fn wrap(event: &'a AnalyticsEvent, producer_id: String, produced_at: u64) -> Self {
+        let message_type = match event {
+            AnalyticsEvent::Block(_) => MessageType::BlockAnalytics,
+            AnalyticsEvent::Reorg(_) => MessageType::Reorg,
+        };
+
+        Self {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            message_type,
+            producer_id,
+            produced_at,
+            payload: event,
+        }
+    }
+}
+
+impl Envelope<Value> {
+    /// Читает сообщение любой известной версии и приводит его к текущей.
+    pub fn decode(codec: Codec, body: &[u8]) -> Result<Self> {
+        let value: Value = codec.decode(body)?;
+
+        let mut envelope = if value.get("schema_version").is_some() {
+            serde_json::from_value::<Envelope<Value>>(value)?
+        } else {
+            Self::from_legacy(value)?
+        };
+
+        if envelope.schema_version == 0 || envelope.schema_version > CURRENT_SCHEMA_VERSION {
+            return Err(anyhow!(
+                "unsupported schema version {} (this reader supports up to {})",
+                envelope.schema_version, CURRENT_SCHEMA_VERSION
+            ));
+        }
+
+        for upcast in &UPCASTERS[envelope.schema_version as usize - 1..] {
+            upcast(&mut envelope.payload);
+        }
+        envelope.schema_version = CURRENT_SCHEMA_VERSION;
+
+        Ok(envelope)
+    }
+
+    /// В версии 1 не было метаданных: тип определяем по полям, producer и время неизвестны.
+    fn from_legacy(payload: Value) -> Result<Self> {
+        let message_type = if payload.get("block_hash").is_some() {
+            MessageType::BlockAnalytics
+        } else if payload.get("fork_hash").is_some() {
+            MessageType::Reorg
+        } else {
+            return Err(anyhow!("unknown legacy message, neither block analytics nor reorg"));
+        };
+
+        Ok(Self {
+            schema_version: 1,
+            message_type,
+            producer_id: String::new(),
+            produced_at: 0,
+            payload,
+        })
+    }
+
+    pub fn into_event(self) -> Result<AnalyticsEvent> {
+        let event = match self.message_type {
+            MessageType::BlockAnalytics => AnalyticsEvent::Block(serde_json::from_value(self.payload)?),
+            MessageType::Reorg => AnalyticsEvent::Reorg(serde_json::from_value(self.payload)?),
+        };
+
+        Ok(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::infrastructure::db::test_support::block_message;
+
+    #[test]
+    fn upcasts_a_bare_v1_block_message() {
+        // Версия 1 — голый `BlockAnalyticsMessage` без конверта.
+        let legacy = serde_json::to_vec(&block_message(800_000, &"aa".repeat(32), &"bb".repeat(32), "foundry")).unwrap();
+
+        let envelope = Envelope::decode(Codec::Json, &legacy).unwrap();
+
+        assert_eq!(envelope.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(envelope.message_type, MessageType::BlockAnalytics);
+        assert_eq!(envelope.producer_id, "");
+        assert_eq!(envelope.produced_at, 0);
+        match envelope.into_event().unwrap() {
+            AnalyticsEvent::Block(message) => {
+                assert_eq!(message.height, 800_000);
+                assert_eq!(message.coinbase_info.pool_slug.as_deref(), Some("foundry"));
+            }
+            AnalyticsEvent::Reorg(_) => panic!("v1 block decoded as reorg"),
+        }
+    }
+
+    #[test]
+    fn upcasts_a_bare_v1_reorg_event() {
+        let legacy = json!({
+            "fork_height": 800_000,
+            "fork_hash": "cc".repeat(32),
+            "orphaned_blocks": [{ "height": 800_001, "hash": "dd".repeat(32), "guessed_miner": null }],
+            "new_blocks": [],
+            "detected_at": 1_700_000_000u64
+        });
+
+        let envelope = Envelope::decode(Codec::Json, &serde_json::to_vec(&legacy).unwrap()).unwrap();
+
+        assert_eq!(envelope.message_type, MessageType::Reorg);
+        match envelope.into_event().unwrap() {
+            AnalyticsEvent::Reorg(event) => {
+                assert_eq!(event.fork_height, 800_000);
+                assert_eq!(event.orphaned_blocks[0].pool_slug, None);
+            }
+            AnalyticsEvent::Block(_) => panic!("v1 reorg decoded as block"),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_versions_and_payloads() {
+        let newer = json!({
+            "schema_version": CURRENT_SCHEMA_VERSION + 1,
+            "message_type": "block_analytics",
+            "producer_id": "future",
+            "produced_at": 0,
+            "payload": {}
+        });
+        let err = Envelope::decode(Codec::Json, &serde_json::to_vec(&newer).unwrap()).unwrap_err();
+        assert!(err.to_string().contains("unsupported schema version"), "{}", err);
+
+        let unknown_legacy = serde_json::to_vec(&json!({ "height": 1 })).unwrap();
+        assert!(Envelope::decode(Codec::Json, &unknown_legacy).is_err());
+    }
+}
\ No newline at end of file
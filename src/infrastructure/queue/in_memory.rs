@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::info;
+use tokio::sync::watch;
+
+use crate::infrastructure::queue::message_bus::{Delivery, MessageBus, OutgoingMessage, Subscription};
+
+/// Шина внутри процесса: весь конвейер работает в одном бинарнике без брокера.
+/// Повторяет семантику RabbitMQ Streams — offset'ы, сохранённые offset'ы подписчиков и дедупликацию по publishing id,
+/// но ничего не переживает рестарт.
+#[derive(Default)]
+pub struct InMemoryBus {
+    streams: Mutex<HashMap<String, Arc<MemoryStream>>>,
+}
+
+struct MemoryStream {
+    log: Mutex<StreamLog>,
+    /// Текущая длина лога: подписчики ждут её изменения.
+    length: watch::Sender<u64>,
+}
+
+struct StoredMessage {
+    body: Vec<u8>,
+    content_type: Option<String>,
+    headers: BTreeMap<String, String>,
+}
+
+#[derive(Default)]
+struct StreamLog {
+    messages: Vec<Arc<StoredMessage>>,
+    last_publishing_ids: HashMap<String, u64>,
+    /// Следующий id именованного producer'а для сообщений без publishing id.
+    auto_sequences: HashMap<String, u64>,
+    stored_offsets: HashMap<String, u64>,
+}
+
+impl InMemoryBus {
+    pub fn new() -> Self {
+        info!("In-memory message bus initialized, messages won't survive a restart");
+        Self::default()
+    }
+
+    fn stream(&self, stream: &str) -> Result<Arc<MemoryStream>> {
+        let mut streams = self.streams.lock().map_err(|e| anyhow!("In-memory bus is poisoned: {}", e))?;
+
+        let stream = streams.entry(stream.to_string())
+            .or_insert_with(|| Arc::new(MemoryStream {
+                log: Mutex::new(StreamLog::default()),
+                length: watch::Sender::new(0),
+            }));
+
+        Ok(Arc::clone(stream))
+    }
+}
+
+impl MemoryStream {
+    fn log(&self) -> Result<std::sync::MutexGuard<'_, StreamLog>> {
+        self.log.lock().map_err(|e| anyhow!("In-memory stream is poisoned: {}", e))
+    }
+}
+
+#[async_trait]
+impl MessageBus for InMemoryBus {
+    fn name(&self) -> &'static str {
+        "in-memory"
+    }
+
+    async fn publish(&self, stream: &str, messages: Vec<OutgoingMessage>) -> Result<()> {
+        let stream = self.stream(stream)?;
+        let mut log = stream.log()?;
+
+        for message in messages {
+            // Брокер дедуплицирует только именованные producer'ы.
+            if let Some(producer) = message.producer {
+                let last_publishing_id = log.last_publishing_ids.get(&producer).copied();
+                // Как клиент rabbitmq-stream-client: без publishing id producer нумерует сообщения сам,
+                // начиная с последнего id, сохранённого брокером, так что первое такое сообщение — дубликат.
+                let publishing_id = match message.publishing_id {
+                    Some(publishing_id) => publishing_id,
+                    None => {
+                        let sequence = log.auto_sequences.entry(producer.clone()).or_insert(last_publishing_id.unwrap_or(0));
+                        *sequence += 1;
+                        *sequence - 1
+                    }
+                };
+
+                // Id не больше последнего принятого от этого producer'а — дубликат.
+                if last_publishing_id.is_some_and(|last| publishing_id <= last) {
+                    continue;
+                }
+                log.last_publishing_ids.insert(producer, publishing_id);
+            }
+
+            log.messages.push(Arc::new(StoredMessage {
+                body: message.body,
+                content_type: message.content_type,
+                headers: message.headers,
+            }));
+        }
+
+        stream.length.send_replace(log.messages.len() as u64);
+
+        Ok(())
+    }
+
+    async fn subscribe(&self, stream: &str, subscriber: &str) -> Result<Box<dyn Subscription>> {
+        let stream = self.stream(stream)?;
+        let next_offset = stream.log()?.stored_offsets.get(subscriber).map_or(0, |offset| offset + 1);
+        let length = stream.length.subscribe();
+
+        Ok(Box::new(InMemorySubscription {
+            stream,
+            subscriber: subscriber.to_string(),
+            next_offset,
+            length,
+        }))
+    }
+}
+
+struct InMemorySubscription {
+    stream: Arc<MemoryStream>,
+    subscriber: String,
+    next_offset: u64,
+    length: watch::Receiver<u64>,
+}
+
+#[async_trait]
+impl Subscription for InMemorySubscription {
+    async fn next(&mut self) -> Result<Option<Delivery>> {
+        loop {
+            let message = self.stream.log()?.messages.get(self.next_offset as usize).cloned();
+            if let Some(message) = message {
+                let offset = self.next_offset;
+                self.next_offset += 1;
+                return Ok(Some(Delivery {
+                    offset,
+                    body: message.body.clone(),
+                    content_type: message.content_type.clone(),
+                    headers: message.headers.clone(),
+                }));
+            }
+
+            if self.length.changed().await.is_err() {
+                return Ok(None);
+            }
+        }
+    }
+
+    async fn commit(&mut self, offset: u64) -> Result<()> {
+        self.stream.log()?.stored_offsets.insert(self.subscriber.clone(), offset);
+        Ok(())
+    }
+
+    async fn close(self: Box<Self>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn message(body: &str, producer: &str, publishing_id: u64) -> OutgoingMessage {
+        OutgoingMessage {
+            producer: Some(producer.to_string()),
+            publishing_id: Some(publishing_id),
+            ..OutgoingMessage::new(body.as_bytes().to_vec())
+        }
+    }
+
+    async fn next_body(subscription: &mut Box<dyn Subscription>) -> (u64, String) {
+        let delivery = subscription.next().await.unwrap().unwrap();
+        (delivery.offset, String::from_utf8(delivery.body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn drops_messages_with_a_repeated_publishing_id() {
+        let bus = InMemoryBus::new();
+
+        bus.publish("analytics", vec![message("a", "live", 2), message("b", "live", 4)]).await.unwrap();
+        // Повтор батча после сбоя и более старый id того же producer'а отбрасываются, у другого producer'а свой счётчик.
+        bus.publish("analytics", vec![message("a", "live", 2), message("b", "live", 4), message("old", "live", 3)]).await.unwrap();
+        bus.publish("analytics", vec![message("reorg", "live-reorg", 2), message("c", "live", 6)]).await.unwrap();
+
+        let mut subscription = bus.subscribe("analytics", "reader").await.unwrap();
+        assert_eq!(next_body(&mut subscription).await, (0, "a".to_string()));
+        assert_eq!(next_body(&mut subscription).await, (1, "b".to_string()));
+        assert_eq!(next_body(&mut subscription).await, (2, "reorg".to_string()));
+        assert_eq!(next_body(&mut subscription).await, (3, "c".to_string()));
+        assert!(tokio::time::timeout(Duration::from_millis(50), subscription.next()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn numbers_messages_without_publishing_id_from_the_last_stored_id() {
+        let bus = InMemoryBus::new();
+        bus.publish("analytics", vec![message("live", "live", 10)]).await.unwrap();
+
+        let unnumbered = |body: &str, producer: Option<&str>| OutgoingMessage {
+            producer: producer.map(str::to_string),
+            ..OutgoingMessage::new(body.as_bytes().to_vec())
+        };
+        // Именованный producer без id получает 10 и 11: первое сообщение отбрасывается, а последний id сдвигается.
+        bus.publish("analytics", vec![unnumbered("dropped", Some("live")), unnumbered("auto", Some("live"))]).await.unwrap();
+        bus.publish("analytics", vec![message("stale", "live", 11)]).await.unwrap();
+        // Producer без имени не дедуплицируется.
+        bus.publish("analytics", vec![unnumbered("a", None), unnumbered("a", None)]).await.unwrap();
+
+        let mut subscription = bus.subscribe("analytics", "reader").await.unwrap();
+        assert_eq!(next_body(&mut subscription).await, (0, "live".to_string()));
+        assert_eq!(next_body(&mut subscription).await, (1, "auto".to_string()));
+        assert_eq!(next_body(&mut subscription).await, (2, "a".to_string()));
+        assert_eq!(next_body(&mut subscription).await, (3, "a".to_string()));
+        assert!(tokio::time::timeout(Duration::from_millis(50), subscription.next()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn resumes_after_the_committed_offset() {
+        let bus = InMemoryBus::new();
+        bus.publish("analytics", ["a", "b", "c"].map(|body| OutgoingMessage::new(body.as_bytes().to_vec())).to_vec()).await.unwrap();
+
+        let mut subscription = bus.subscribe("analytics", "reader").await.unwrap();
+        assert_eq!(next_body(&mut subscription).await, (0, "a".to_string()));
+        assert_eq!(next_body(&mut subscription).await, (1, "b".to_string()));
+        // "b" прочитано, но не подтверждено: после переподписки оно придёт снова.
+        subscription.commit(0).await.unwrap();
+        subscription.close().await;
+
+        let mut subscription = bus.subscribe("analytics", "reader").await.unwrap();
+        assert_eq!(next_body(&mut subscription).await, (1, "b".to_string()));
+
+        // Offset хранится отдельно для каждого подписчика.
+        let mut other = bus.subscribe("analytics", "inspector").await.unwrap();
+        assert_eq!(next_body(&mut other).await, (0, "a".to_string()));
+
+        // Подписчик, дочитавший стрим, получает только новые сообщения.
+        assert_eq!(next_body(&mut subscription).await, (2, "c".to_string()));
+        subscription.commit(2).await.unwrap();
+        let mut subscription = bus.subscribe("analytics", "reader").await.unwrap();
+        bus.publish("analytics", vec![OutgoingMessage::new(b"d".to_vec())]).await.unwrap();
+        assert_eq!(next_body(&mut subscription).await, (3, "d".to_string()));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::config::config::{Config, MessageBusKind};
+use crate::infrastructure::queue::in_memory::InMemoryBus;
+use crate::infrastructure::queue::stream_rabbitmq::RabbitMQClient;
+
+/// Сообщение для публикации в стрим.
+#[derive(Debug, Clone)]
+pub struct OutgoingMessage {
+    pub body: Vec<u8>,
+    /// Producer, от имени которого публикуется сообщение. `None` — producer без имени:
+    /// брокер дедуплицирует только именованные producer'ы, поэтому такие сообщения никогда не отбрасываются.
+    pub producer: Option<String>,
+    /// Вместе с `producer` повтор с тем же id от того же producer'а отбрасывается как дубликат.
+    /// Именованный producer без id нумерует сообщения сам, продолжая последний id, сохранённый брокером.
+    pub publishing_id: Option<u64>,
+    /// MIME-тип тела, по нему подписчик выбирает декодер.
+    pub content_type: Option<String>,
+    /// Строковые заголовки сообщения (application properties в AMQP).
+    pub headers: BTreeMap<String, String>,
+}
+
+impl OutgoingMessage {
+    pub fn new(body: Vec<u8>) -> Self {
+        Self { body, producer: None, publishing_id: None, content_type: None, headers: BTreeMap::new() }
+    }
+}
+
+/// Сообщение, прочитанное подписчиком.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub offset: u64,
+    pub body: Vec<u8>,
+    pub content_type: Option<String>,
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Publish/subscribe поверх стримов: сообщения хранятся по порядку, у каждого свой offset.
+#[async_trait]
+pub trait MessageBus: Send + Sync {
+    /// Имя backend'а для логов.
+    fn name(&self) -> &'static str;
+
+    /// Публикует сообщения по порядку. Ошибка означает, что часть сообщений могла не дойти.
+    async fn publish(&self, stream: &str, messages: Vec<OutgoingMessage>) -> Result<()>;
+
+    /// Подписка под именем `subscriber` продолжает со следующего после сохранённого offset'а,
+    /// а если offset'а ещё нет — с начала стрима.
+    async fn subscribe(&self, stream: &str, subscriber: &str) -> Result<Box<dyn Subscription>>;
+}
+
+#[async_trait]
+pub trait Subscription: Send {
+    /// Следующее сообщение; `None` — подписка закрыта.
+    async fn next(&mut self) -> Result<Option<Delivery>>;
+
+    /// Сохраняет offset обработанного сообщения под именем подписчика.
+    async fn commit(&mut self, offset: u64) -> Result<()>;
+
+    async fn close(self: Box<Self>);
+}
+
+pub async fn build_message_bus(config: &Config) -> Result<Arc<dyn MessageBus>> {
+    let message_bus: Arc<dyn MessageBus> = match config.get_message_bus() {
+        MessageBusKind::RabbitMq => Arc::new(RabbitMQClient::new(config.get_rabbitmq_config()).await?),
+        MessageBusKind::InMemory => Arc::new(InMemoryBus::new()),
+    };
+
+    Ok(message_bus)
+}
\ No newline at end of file
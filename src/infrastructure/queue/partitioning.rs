@@ -0,0 +1,52 @@
+use crate::config::config::StreamsConfig;
+use crate::infrastructure::queue::queue_service::AnalyticsEvent;
+use crate::utils::murmur3::murmur3_32;
+
+/// Seed, с которым клиенты RabbitMQ хешируют ключ маршрутизации super stream'а.
+const ROUTING_HASH_SEED: u32 = 104_729;
+
+/// Ключ для блоков, пул которых не определён.
+const UNKNOWN_POOL_ROUTING_KEY: &str = "unknown";
+
+/// Партиции стрима аналитики — эмуляция super stream'а: в rabbitmq-stream-client 0.4.4 нет API super stream'ов.
+/// При `partitions > 1` это обычные стримы `{analytics}-0 .. {analytics}-{N-1}`, названные как партиции super stream'а,
+/// а блок попадает в партицию по хешу slug'а пула на стороне сервиса.
+/// Так сообщения одного пула остаются упорядоченными, а разные пулы читаются параллельно.
+/// Партиция — хеш по модулю `partitions`, поэтому смена числа партиций переносит пулы в другие стримы.
+#[derive(Debug, Clone)]
+pub struct AnalyticsPartitions {
+    streams: Vec<String>,
+}
+
+impl AnalyticsPartitions {
+    pub fn new(streams: &StreamsConfig) -> Self {
+        let analytics = streams.get_analytics();
+        let partitions = streams.get_partitions();
+
+        let streams = if partitions <= 1 {
+            vec![analytics.to_string()]
+        } else {
+            (0..partitions).map(|partition| format!("{}-{}", analytics, partition)).collect()
+        };
+
+        Self { streams }
+    }
+
+    pub fn streams(&self) -> &[String] {
+        &self.streams
+    }
+
+    /// Reorg-событие уходит во все партиции: каждый читатель применит его после уже прочитанных блоков своей партиции,
+    /// поэтому осиротевшие блоки будут помечены, в какой бы партиции они ни лежали. Запись reorg'а идемпотентна,
+    /// а блок, который отстающая партиция дочитала уже после reorg'а, сразу пишется orphaned (см. `save_block_and_coinbase`).
+    pub fn route(&self, event: &AnalyticsEvent) -> &[String] {
+        match event {
+            AnalyticsEvent::Block(message) => {
+                let routing_key = message.coinbase_info.pool_slug.as_deref().unwrap_or(UNKNOWN_POOL_ROUTING_KEY);
+                let partition = murmur3_32(routing_key.as_bytes(), ROUTING_HASH_SEED) as usize % self.streams.len();
+                std::slice::from_ref(&self.streams[partition])
+            }
+            AnalyticsEvent::Reorg(_) => &self.streams,
+        }
+    }
+}
\ No newline at end of file
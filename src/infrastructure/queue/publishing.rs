@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+/// Высота занимает биты 1..40, младший бит — флаг reorg-события.
+const HEIGHT_SHIFT: u32 = 1;
+const BACKFILL_ID_BASE: u64 = 1 << 40;
+const REORG_FLAG: u64 = 1;
+
+/// Через какой producer и с каким publishing id отправить сообщение.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublishTarget {
+    /// `None` — основной producer стрима `mining-analytics`.
+    #[serde(default)]
+    pub producer: Option<String>,
+    pub publishing_id: u64,
+}
+
+/// Как watcher нумерует сообщения для дедупликации на стороне брокера.
+/// RabbitMQ Streams отбрасывает сообщение, если его publishing id не больше последнего принятого
+/// от producer'а с тем же именем, поэтому id обязаны расти в порядке отправки.
+/// Id зависят только от высоты и имени producer'а, а не от локального состояния: потеряв `state_dir`,
+/// watcher не начнёт отправлять id ниже уже принятых брокером.
+#[derive(Debug, Clone, Default)]
+pub enum PublishLane {
+    /// `height << 1`, у reorg-события ещё `| 1`.
+    #[default]
+    Live,
+    /// Reorg-событие и блоки новой ветки на высотах, которые основной producer уже занял.
+    /// Нумерация как у `Live`, но у каждого reorg'а свой producer.
+    Reorg { producer_name: String },
+    /// Backfill идёт вниз по высотам, поэтому `id = 2^40 - height`. У каждого диапазона свой producer.
+    Backfill { producer_name: String },
+}
+
+impl PublishLane {
+    /// Producer reorg'а называется по хэшу блока, на котором reorg обнаружен: повторно обнаруженный
+    /// после рестарта тот же reorg попадёт в тот же producer и будет отброшен дедупликацией.
+    pub fn for_reorg(&self, live_producer_name: &str, new_tip_hash: &str) -> PublishLane {
+        match self {
+            PublishLane::Backfill { .. } => self.clone(),
+            PublishLane::Live | PublishLane::Reorg { .. } => PublishLane::Reorg {
+                producer_name: format!("{}-reorg-{}", live_producer_name, new_tip_hash),
+            },
+        }
+    }
+
+    pub fn block_target(&self, height: u64) -> PublishTarget {
+        match self {
+            PublishLane::Live => PublishTarget {
+                producer: None,
+                publishing_id: height << HEIGHT_SHIFT,
+            },
+            PublishLane::Reorg { producer_name } => PublishTarget {
+                producer: Some(producer_name.clone()),
+                publishing_id: height << HEIGHT_SHIFT,
+            },
+            PublishLane::Backfill { producer_name } => PublishTarget {
+                producer: Some(producer_name.clone()),
+                publishing_id: BACKFILL_ID_BASE - height,
+            },
+        }
+    }
+
+    /// Reorg-событие уходит перед блоками новой ветки, которые выше точки форка, поэтому его id меньше их id.
+    pub fn reorg_target(&self, fork_height: u64) -> PublishTarget {
+        let target = self.block_target(fork_height);
+
+        match self {
+            PublishLane::Live | PublishLane::Reorg { .. } => PublishTarget {
+                publishing_id: target.publishing_id | REORG_FLAG,
+                ..target
+            },
+            PublishLane::Backfill { .. } => target,
+        }
+    }
+}
\ No newline at end of file
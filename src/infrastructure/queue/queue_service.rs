@@ -1,21 +1,39 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::path::Path;
+use std::time::Duration;
 
 use log::{error, info};
 
 use serde::{Deserialize, Serialize};
 
 use anyhow::Result;
-use futures_util::TryStreamExt;
-use rabbitmq_stream_client::Consumer;
+use chrono::Utc;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Sender;
-use tokio::task::JoinHandle;
+use tokio::time::{sleep, sleep_until, Instant};
+use tokio_util::sync::CancellationToken;
 
-use crate::domain::block::Block;
+use crate::config::config::Config;
+use crate::domain::block::{Block, BlockRef};
+use crate::domain::fee_stats::BlockFeeStats;
+use crate::domain::notification::Notification;
+use crate::domain::reward_anomaly::RewardAnomaly;
 use crate::domain::transaction::Transaction;
-use crate::infrastructure::queue::stream_rabbitmq::RabbitMQClient;
+use crate::infrastructure::db::postgres::{write_until_saved, DbWrite, WriteOutcome};
+use crate::infrastructure::queue::publishing::PublishTarget;
+use crate::infrastructure::queue::spool::MessageSpool;
+use crate::infrastructure::queue::codec::Codec;
+use crate::infrastructure::queue::dead_letter::{DeadLetterQueue, DeadLetterStage};
+use crate::infrastructure::queue::envelope::Envelope;
+use crate::infrastructure::queue::message_bus::{MessageBus, OutgoingMessage, Subscription};
+use crate::infrastructure::queue::partitioning::AnalyticsPartitions;
+use crate::utils::coinbase_commitment::{CoinbaseCommitment, CommitmentKind};
+use crate::utils::merged_mining::{detect_merged_mining, AuxPowHeader};
+use crate::utils::pool_identifier::PoolMatch;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockAnalyticsMessage {
     pub height: u32,
     pub block_hash: String,
@@ -24,45 +42,57 @@ pub struct BlockAnalyticsMessage {
     pub merkle_root: String,
     pub difficulty: f64,
     pub transactions_count: u64,
-    pub coinbase_info: CoinbaseInfo
+    pub coinbase_info: CoinbaseInfo,
+    #[serde(default)]
+    pub fee_stats: Option<BlockFeeStats>,
+    #[serde(default)]
+    pub reward_anomaly: Option<RewardAnomaly>
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CoinbaseInfo {
+    /// Нет в сообщениях, отправленных до его появления.
+    #[serde(default)]
+    pub txid: Option<String>,
     pub main_reward: Option<i64>,
     pub miner_address: Option<String>,
     pub full_reward: i64,
     // #[serde(default)]
     pub fee: i64,
     pub guessed_miner: String,
-    pub rewards_and_addresses: Vec<(i64, String)>
+    #[serde(default)]
+    pub pool_name: Option<String>,
+    #[serde(default)]
+    pub pool_slug: Option<String>,
+    #[serde(default)]
+    pub pool_match_method: Option<String>,
+    pub rewards_and_addresses: Vec<(i64, String)>,
+    #[serde(default)]
+    pub outputs: Vec<CoinbaseOutput>,
+    #[serde(default)]
+    pub commitments: Vec<CoinbaseCommitment>,
+    /// Маркеры merge mining: сети с OP_RETURN-тегом по отдельности, все aux-pow сети — одним `aux_pow`.
+    #[serde(default)]
+    pub merged_mining: Vec<CommitmentKind>,
+    #[serde(default)]
+    pub aux_pow: Option<AuxPowHeader>
 }
 
-pub struct QueueService {
-    pub(crate) rabbitmq_client: Arc<RabbitMQClient>,
-    pub sender: Sender<BlockAnalyticsMessage>,
-    #[allow(dead_code)]
-    worker_handle: Option<JoinHandle<()>>
-}
-
-impl QueueService {
-    pub fn new(rabbitmq_client: Arc<RabbitMQClient>) -> Self {
-        let (sender, receiver) = mpsc::channel::<BlockAnalyticsMessage>(1000);
-
-        let worker_rabbitmq = Arc::clone(&rabbitmq_client);
-        let worker_handle = tokio::spawn(async move {
-            Self::queue_worker(receiver, worker_rabbitmq).await;
-        });
+impl BlockAnalyticsMessage {
+    pub fn new(
+        block: &Block,
+        coinbase: &Transaction,
+        guessed_miner: String,
+        pool: &PoolMatch,
+        fee_stats: Option<&BlockFeeStats>,
+        reward_anomaly: Option<&RewardAnomaly>,
+    ) -> Self {
+        let commitments = CoinbaseCommitment::decode_outputs(coinbase.get_vouts());
+        let coinbase_script = hex::decode(coinbase.get_vin_scriptsig()).unwrap_or_default();
+        let merged_mining = detect_merged_mining(&coinbase_script, &commitments);
+        let aux_pow = AuxPowHeader::parse(&coinbase_script);
 
-        Self {
-            rabbitmq_client,
-            sender,
-            worker_handle: Some(worker_handle)
-        }
-    }
-
-    pub async fn send_block_analytics(&self, block: &Block, coinbase: &Transaction, guessed_miner: String) -> Result<()> {
-        let analytics_message = BlockAnalyticsMessage {
+        BlockAnalyticsMessage {
             height: block.get_height(),
             block_hash: block.get_id().to_string(),
             timestamp: block.get_timestamp(),
@@ -71,18 +101,129 @@ impl QueueService {
             difficulty: block.get_difficulty(),
             transactions_count: block.get_tx_count(),
             coinbase_info: CoinbaseInfo {
+                txid: Some(coinbase.get_txid().to_string()),
                 main_reward: coinbase.get_main_reward_value(),
                 miner_address: coinbase.get_main_reward_address().and_then(|addr| addr.clone()),
-                fee: coinbase.calculate_fee().unwrap_or(0),
+                // Если блок разобран целиком, берём реальную сумму комиссий, а не coinbase минус субсидия.
+                fee: fee_stats.map(|stats| stats.total_fees)
+                    .or_else(|| coinbase.calculate_fee())
+                    .unwrap_or(0),
                 full_reward: coinbase.get_full_reward_value(),
                 guessed_miner,
+                pool_name: Some(pool.name.clone()),
+                pool_slug: Some(pool.slug.clone()),
+                pool_match_method: Some(pool.method.as_str().to_string()),
                 rewards_and_addresses: coinbase.get_rewards_value_and_address(),
+                outputs: coinbase.get_vouts().iter()
+                    .enumerate()
+                    .map(|(vout_index, vout)| CoinbaseOutput {
+                        vout_index: vout_index as u32,
+                        value: vout.get_value(),
+                        script_type: vout.get_scriptpubkey_type().to_string(),
+                        address: vout.get_scriptpubkey_address().clone(),
+                        scriptpubkey: vout.get_scriptpubkey().to_string(),
+                    })
+                    .collect(),
+                commitments,
+                merged_mining,
+                aux_pow,
             },
+            fee_stats: fee_stats.cloned(),
+            reward_anomaly: reward_anomaly.cloned(),
+        }
+    }
+}
+
+/// Выход coinbase как есть, включая нулевые (OP_RETURN) и выходы без адреса.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoinbaseOutput {
+    pub vout_index: u32,
+    pub value: i64,
+    pub script_type: String,
+    pub address: Option<String>,
+    pub scriptpubkey: String,
+}
+
+/// Блоки цепочки, которые заменили друг друга при reorg. `orphaned_blocks` — вытесненная ветка.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReorgEvent {
+    pub fork_height: u64,
+    pub fork_hash: String,
+    pub orphaned_blocks: Vec<BlockRef>,
+    pub new_blocks: Vec<BlockRef>,
+    pub detected_at: u64,
+}
+
+impl ReorgEvent {
+    pub fn depth(&self) -> usize {
+        self.orphaned_blocks.len()
+    }
+}
+
+/// Всё, что публикуется в стрим `mining-analytics`.
+/// `untagged`: тип сообщения хранится в конверте, а payload остаётся тем же JSON, что и в версии 1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+pub enum AnalyticsEvent {
+    Block(BlockAnalyticsMessage),
+    Reorg(ReorgEvent),
+}
+
+/// Событие в очереди на отправку вместе с тем, через какой producer и с каким publishing id его отправлять.
+/// В таком виде оно лежит и в spool'е, чтобы повторная отправка не обходила дедупликацию.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuedEvent {
+    #[serde(flatten)]
+    pub target: PublishTarget,
+    pub event: AnalyticsEvent,
+    /// Unix-время постановки в очередь; у записей spool'а старых версий его нет.
+    #[serde(default)]
+    pub produced_at: Option<u64>,
+}
+
+impl QueuedEvent {
+    pub fn new(target: PublishTarget, event: AnalyticsEvent) -> Self {
+        Self {
+            target,
+            event,
+            produced_at: Some(Utc::now().timestamp() as u64),
+        }
+    }
+}
+
+/// Файл spool'а в `state_dir`.
+const ANALYTICS_SPOOL_FILE: &str = "analytics_spool.jsonl";
+
+pub struct QueueService {
+    message_bus: Arc<dyn MessageBus>,
+    notifications_stream: String,
+    pub sender: Sender<QueuedEvent>,
+}
+
+impl QueueService {
+    /// Worker возвращается отдельно: его запускает супервизор в `SchedulerManager`.
+    pub fn new(message_bus: Arc<dyn MessageBus>, config: &Config) -> (Self, QueueWorker) {
+        let (sender, receiver) = mpsc::channel::<QueuedEvent>(1000);
+
+        let queue_worker = QueueWorker::new(Arc::clone(&message_bus), receiver, config);
+
+        let queue_service = Self {
+            message_bus,
+            notifications_stream: config.get_rabbitmq_config().get_streams().get_notifications().to_string(),
+            sender,
         };
 
-        match self.sender.send(analytics_message).await {
+        (queue_service, queue_worker)
+    }
+
+    pub async fn send_block_analytics(&self, analytics_message: BlockAnalyticsMessage, target: PublishTarget) -> Result<()> {
+        let height = analytics_message.height;
+        let queued_event = QueuedEvent::new(target, AnalyticsEvent::Block(analytics_message));
+
+        match self.sender.send(queued_event).await {
             Ok(_) => {
-                info!("Block analytics queue for block {}", block.get_height());
+                info!("Block analytics queue for block {}", height);
                 Ok(())
             }
             Err(e) => {
@@ -92,69 +233,613 @@ impl QueueService {
         }
     }
 
-    pub async fn queue_worker(mut receiver: mpsc::Receiver<BlockAnalyticsMessage>, rabbitmq_client: Arc<RabbitMQClient>) {
-        info!("Queue worker started!");
-        let batch_size: usize = 10;
+    pub async fn send_reorg_event(&self, reorg_event: ReorgEvent, target: PublishTarget) -> Result<()> {
+        let fork_height = reorg_event.fork_height;
+        let queued_event = QueuedEvent::new(target, AnalyticsEvent::Reorg(reorg_event));
+
+        match self.sender.send(queued_event).await {
+            Ok(_) => {
+                info!("Reorg event queued for fork at height {}", fork_height);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to queue reorg event: {:?}", e);
+                Err(anyhow::anyhow!("Channel send error: {}", e))
+            }
+        }
+    }
 
-        let mut batch_analytics_messages = Vec::new();
+    /// Уведомления редкие, поэтому отправляются сразу, без батчинга. Их читают внешние сервисы, поэтому всегда JSON.
+    /// Producer без имени: у уведомлений нет детерминированного id, а именованный producer после рестарта
+    /// продолжил бы нумерацию с последнего id брокера, и первое уведомление отбросилось бы как дубликат.
+    pub async fn send_notification(&self, notification: &Notification) -> Result<()> {
+        let mut message = OutgoingMessage::new(Codec::Json.encode(notification)?);
+        message.content_type = Some(Codec::Json.content_type().to_string());
+        self.message_bus.publish(&self.notifications_stream, vec![message]).await
+    }
 
-        while let Some(message) = receiver.recv().await {
-            batch_analytics_messages.push(message);
+    pub fn message_bus(&self) -> Arc<dyn MessageBus> {
+        Arc::clone(&self.message_bus)
+    }
 
-            if batch_analytics_messages.len() >= batch_size {
-                match rabbitmq_client.send_batch_analytics_messages(&batch_analytics_messages).await {
-                    Ok(_) => {
-                        info!("The analytics messages were sent successfully");
-                        batch_analytics_messages.clear();
-                    }
-                    Err(err) => {
-                        error!("The analytics messages were be sent with the error: {:?}", err);
-                        info!("Messages from batch_analytics_messages: {:?} deleted.", batch_analytics_messages);
-                        batch_analytics_messages.clear();
+    /// Читает `mining-analytics` и отдаёт события на запись в БД. Offset сохраняется на сервере только после
+    /// успешной записи или переноса в DLQ, поэтому после рестарта чтение продолжается с первого необработанного сообщения (at-least-once).
+    /// Неразобранные сообщения и те, что БД отклонила `max_db_write_attempts` раз, уходят в `mining-analytics-dlq`.
+    /// При ошибке супервизор пересоздаст consumer и продолжит с сохранённого offset'а.
+    /// По `shutdown` дописывает текущее сообщение, сохраняет его offset и закрывает consumer.
+    pub async fn read_analytics_messages(
+        mut subscription: Box<dyn Subscription>,
+        db_sender: Sender<DbWrite>,
+        dead_letters: &DeadLetterQueue,
+        max_db_write_attempts: u32,
+        shutdown: CancellationToken,
+    ) -> Result<()> {
+        loop {
+            let delivery = tokio::select! {
+                delivery = subscription.next() => delivery,
+                _ = shutdown.cancelled() => break,
+            };
+            let delivery = delivery
+                .map_err(|err| anyhow::anyhow!("Error reading mining-analytics delivery: {}", err))?
+                .ok_or_else(|| anyhow::anyhow!("mining-analytics subscription closed"))?;
+            let offset = delivery.offset;
+
+            // Декодер выбирается по content-type сообщения: в стрим могут писать producer'ы с разными кодеками.
+            let analytics_event = Codec::from_content_type(delivery.content_type.as_deref())
+                .and_then(|codec| Envelope::decode(codec, &delivery.body))
+                .and_then(Envelope::into_event);
+
+            let dead_letter = match analytics_event {
+                Ok(analytics_event) => {
+                    let subject = format!("message at offset {}", offset);
+                    match write_until_saved(&db_sender, analytics_event, &subject, max_db_write_attempts, &shutdown).await? {
+                        WriteOutcome::Saved => None,
+                        WriteOutcome::Rejected(reason) => Some((DeadLetterStage::Persist, reason)),
+                        // Не записано: offset не сохраняем, после рестарта сообщение придёт снова.
+                        WriteOutcome::Interrupted => break,
                     }
                 }
+                // Битое сообщение повторно читать бессмысленно: откладываем его в DLQ и двигаем offset.
+                Err(err) => {
+                    error!("Error parse block analytic message at offset {}: {}", offset, err);
+                    Some((DeadLetterStage::Decode, format!("{:#}", err)))
+                }
+            };
+
+            // Не удалось положить в DLQ — offset не сохраняем, супервизор перезапустит чтение с этого сообщения.
+            if let Some((stage, reason)) = dead_letter {
+                dead_letters.send(&delivery, stage, &reason).await?;
+            }
+
+            if let Err(err) = subscription.commit(offset).await {
+                error!("Couldn't store mining-analytics offset {}: {}", offset, err);
             }
         }
 
-        info!("Queue worker stopped");
+        info!("mining-analytics reader stopped");
+        subscription.close().await;
+
+        Ok(())
+    }
+
+
+}
+
+/// Отправляет `mining-analytics` батчами. Неудачный батч повторяется с экспоненциальной задержкой,
+/// а после `max_send_attempts` попыток пишется в spool на диске и отправляется заново, когда брокер вернётся.
+pub struct QueueWorker {
+    message_bus: Arc<dyn MessageBus>,
+    receiver: mpsc::Receiver<QueuedEvent>,
+    partitions: AnalyticsPartitions,
+    /// Producer для сообщений без своего producer'а в `PublishTarget`.
+    producer_name: String,
+    codec: Codec,
+    batch_size: usize,
+    max_latency: Duration,
+    max_send_attempts: u32,
+    retry_initial_delay: Duration,
+    retry_max_delay: Duration,
+    spool: Option<MessageSpool>,
+}
+
+impl QueueWorker {
+    fn new(message_bus: Arc<dyn MessageBus>, receiver: mpsc::Receiver<QueuedEvent>, config: &Config) -> Self {
+        let rabbitmq_config = config.get_rabbitmq_config();
+        let spool_path = Path::new(config.get_state_dir()).join(ANALYTICS_SPOOL_FILE);
+
+        let spool = MessageSpool::open(spool_path)
+            .inspect(|spool| {
+                if spool.depth() > 0 {
+                    info!("Analytics spool depth: {} messages waiting in {}", spool.depth(), spool.path().display());
+                }
+            })
+            .inspect_err(|e| error!("Spool disabled, failed batches will be dropped: {}", e))
+            .ok();
+
+        Self {
+            message_bus,
+            receiver,
+            partitions: AnalyticsPartitions::new(rabbitmq_config.get_streams()),
+            producer_name: rabbitmq_config.get_streams().get_producer_name(),
+            codec: rabbitmq_config.get_codec(),
+            batch_size: rabbitmq_config.get_batch_size().max(1),
+            max_latency: Duration::from_millis(rabbitmq_config.get_batch_max_latency_ms()),
+            max_send_attempts: rabbitmq_config.get_max_send_attempts().max(1),
+            retry_initial_delay: Duration::from_millis(rabbitmq_config.get_retry_initial_delay_ms()),
+            retry_max_delay: Duration::from_millis(rabbitmq_config.get_retry_max_delay_ms()),
+            spool,
+        }
+    }
+
+    fn close_receiver(&mut self) {
+        info!("Queue worker is shutting down, {} analytics messages left in the channel", self.receiver.len());
+        self.receiver.close();
+    }
+
+    /// У каждого сообщения свой producer и publishing id, по ним брокер отбрасывает дубликаты.
+    /// Дедупликация ведётся отдельно в каждой партиции, а id одного producer'а растут, поэтому растут и внутри партиции.
+    async fn publish_batch(
+        message_bus: &dyn MessageBus,
+        partitions: &AnalyticsPartitions,
+        producer_name: &str,
+        codec: Codec,
+        batch_analytics_messages: &[QueuedEvent],
+    ) -> Result<()> {
+        let mut messages_by_stream: BTreeMap<&str, Vec<OutgoingMessage>> = BTreeMap::new();
+
+        for queued_event in batch_analytics_messages {
+            let producer_id = queued_event.target.producer.clone()
+                .unwrap_or_else(|| producer_name.to_string());
+            let produced_at = queued_event.produced_at.unwrap_or_else(|| Utc::now().timestamp() as u64);
+            let envelope = Envelope::wrap(&queued_event.event, producer_id.clone(), produced_at);
+
+            let message = OutgoingMessage {
+                producer: Some(producer_id),
+                publishing_id: Some(queued_event.target.publishing_id),
+                content_type: Some(codec.content_type().to_string()),
+                ..OutgoingMessage::new(codec.encode(&envelope)?)
+            };
+
+            for stream in partitions.route(&queued_event.event) {
+                messages_by_stream.entry(stream).or_default().push(message.clone());
+            }
+        }
+
+        for (stream, messages) in messages_by_stream {
+            message_bus.publish(stream, messages).await?;
+        }
+
+        Ok(())
     }
 
-    pub async fn read_messages_from_rabbitmq_mining_analytics(mut consumer: Consumer, db_sender: Sender<BlockAnalyticsMessage>) {
+    /// Глубина spool'а для `TaskRegistry`; `None`, если spool не открылся.
+    pub fn spool_depth_gauge(&self) -> Option<Arc<AtomicUsize>> {
+        self.spool.as_ref().map(MessageSpool::depth_gauge)
+    }
+
+    fn spool_depth(&self) -> usize {
+        self.spool.as_ref().map_or(0, MessageSpool::depth)
+    }
+
+    /// Батч уходит, когда набрал `batch_size` сообщений или когда первое сообщение в нём ждёт дольше `max_latency`.
+    /// Пока spool не пуст, по тому же таймеру пробуем его отправить. Когда канал закрывается, остаток отправляется сразу.
+    /// Receiver живёт в worker'е, поэтому после перезапуска супервизором чтение продолжается из того же канала.
+    /// По `shutdown` канал закрывается для новых сообщений, а уже поставленные в очередь дочитываются и отправляются.
+    pub async fn run(&mut self, shutdown: CancellationToken) -> Result<()> {
+        info!("Queue worker started!");
+
+        let mut batch_analytics_messages = Vec::with_capacity(self.batch_size);
+        let mut flush_deadline: Option<Instant> = None;
+
         loop {
-            let delivery_result = consumer.try_next().await;
-            if let Ok(Some(delivery)) = delivery_result {
-                let message = delivery.message();
-
-                if let Some(data) = message.data() {
-                    if let Ok(json_str) = std::str::from_utf8(data) {
-                        let block_analytic_message_res = serde_json::from_str::<BlockAnalyticsMessage>(json_str);
-                        if let Err(err) = block_analytic_message_res {
-                            error!("Error parse block analytic message: {}", err.to_string());
-                            error!("It isn't a block message: {}", json_str);
-                            continue;
-                        }
-
-                        let block_analytic_message = block_analytic_message_res.unwrap();
-
-                        let _ = db_sender.send(block_analytic_message).await;
-/*                        tokio::spawn(async move {
-                            if let Err(err) = db.save_block_and_coinbase(&block_analytic_message).await {
-                                error!("Error saving block+coinbase atomically: {}", err);
-                            }
-                        });*/
+            if flush_deadline.is_none() && self.spool_depth() > 0 {
+                flush_deadline = Some(Instant::now() + self.max_latency);
+            }
+
+            // `None` — истёк таймер батча.
+            let received = match flush_deadline {
+                Some(deadline) => tokio::select! {
+                    message = self.receiver.recv() => Some(message),
+                    _ = sleep_until(deadline) => None,
+                    _ = shutdown.cancelled(), if !self.receiver.is_closed() => {
+                        self.close_receiver();
+                        continue;
+                    }
+                },
+                None => tokio::select! {
+                    message = self.receiver.recv() => Some(message),
+                    _ = shutdown.cancelled(), if !self.receiver.is_closed() => {
+                        self.close_receiver();
+                        continue;
                     }
+                },
+            };
+
+            let Some(message) = received else {
+                self.flush(&mut batch_analytics_messages, &shutdown).await;
+                flush_deadline = None;
+                continue;
+            };
+
+            let Some(message) = message else {
+                break;
+            };
+
+            batch_analytics_messages.push(message);
+            flush_deadline.get_or_insert_with(|| Instant::now() + self.max_latency);
+
+            if batch_analytics_messages.len() >= self.batch_size {
+                self.flush(&mut batch_analytics_messages, &shutdown).await;
+                flush_deadline = None;
+            }
+        }
+
+        if !batch_analytics_messages.is_empty() {
+            info!("Channel closed, flushing {} remaining analytics messages", batch_analytics_messages.len());
+            self.flush(&mut batch_analytics_messages, &shutdown).await;
+        }
+
+        info!("Queue worker stopped");
+
+        Ok(())
+    }
+
+    /// Сначала spool, чтобы сохранить порядок сообщений. Если spool отправить не удалось, брокер всё ещё недоступен:
+    /// новый батч сразу дописываем в spool, не тратя время на повторы.
+    async fn flush(&mut self, batch_analytics_messages: &mut Vec<QueuedEvent>, shutdown: &CancellationToken) {
+        let broker_available = self.replay_spool().await;
+
+        if batch_analytics_messages.is_empty() {
+            return;
+        }
+
+        if broker_available && self.send_with_retries(batch_analytics_messages, shutdown).await {
+            info!("The analytics messages were sent successfully");
+        } else {
+            self.spool_batch(batch_analytics_messages);
+        }
+
+        batch_analytics_messages.clear();
+    }
+
+    /// Во время остановки не ждём брокер: после первой неудачи батч уходит в spool.
+    async fn send_with_retries(&self, batch_analytics_messages: &[QueuedEvent], shutdown: &CancellationToken) -> bool {
+        let mut delay = self.retry_initial_delay;
+
+        for attempt in 1..=self.max_send_attempts {
+            match Self::publish_batch(&*self.message_bus, &self.partitions, &self.producer_name, self.codec, batch_analytics_messages).await {
+                Ok(_) => return true,
+                Err(err) => {
+                    error!("The analytics messages were be sent with the error (attempt {}/{}): {:?}", attempt, self.max_send_attempts, err);
+                }
+            }
+
+            if shutdown.is_cancelled() {
+                break;
+            }
+
+            if attempt < self.max_send_attempts {
+                sleep(delay).await;
+                delay = (delay * 2).min(self.retry_max_delay);
+            }
+        }
+
+        false
+    }
+
+    fn spool_batch(&mut self, batch_analytics_messages: &[QueuedEvent]) {
+        let Some(spool) = self.spool.as_mut() else {
+            info!("Messages from batch_analytics_messages: {:?} deleted.", batch_analytics_messages);
+            return;
+        };
+
+        match spool.append(batch_analytics_messages) {
+            Ok(_) => info!("{} analytics messages spooled, spool depth: {}", batch_analytics_messages.len(), spool.depth()),
+            Err(e) => {
+                error!("Couldn't spool analytics messages: {}", e);
+                info!("Messages from batch_analytics_messages: {:?} deleted.", batch_analytics_messages);
+            }
+        }
+    }
+
+    /// Отправляет spool порциями по `batch_size`. Возвращает `false`, если брокер так и не принял сообщения.
+    async fn replay_spool(&mut self) -> bool {
+        let Some(spool) = self.spool.as_mut() else {
+            return true;
+        };
+        if spool.depth() == 0 {
+            return true;
+        }
+
+        let spooled: Vec<QueuedEvent> = match spool.read_all() {
+            Ok(spooled) => spooled,
+            Err(e) => {
+                error!("Couldn't read analytics spool: {}", e);
+                return true;
+            }
+        };
+
+        let mut sent = 0;
+        for chunk in spooled.chunks(self.batch_size) {
+            if let Err(err) = Self::publish_batch(&*self.message_bus, &self.partitions, &self.producer_name, self.codec, chunk).await {
+                error!("Spool replay failed, broker is still unavailable: {:?}", err);
+                break;
+            }
+            sent += chunk.len();
+        }
+
+        if sent == 0 {
+            return false;
+        }
+
+        if let Err(e) = spool.replace(&spooled[sent..]) {
+            error!("Couldn't update analytics spool after replay: {}", e);
+        }
+        info!("Replayed {} spooled analytics messages, spool depth: {}", sent, spool.depth());
+
+        sent == spooled.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use serde_json::json;
+    use tokio::task::JoinHandle;
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::infrastructure::db::postgres::Database;
+    use crate::infrastructure::db::test_support::{block_message, TestDatabase};
+    use crate::infrastructure::queue::in_memory::InMemoryBus;
+    use crate::infrastructure::queue::publishing::PublishLane;
+
+    static STATE_DIR_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    /// Конфиг с in-memory шиной и своим `state_dir` для spool'а.
+    fn test_config(partitions: u32) -> (Config, PathBuf) {
+        let state_dir = std::env::temp_dir().join(format!(
+            "analytics_test_{}_{}", std::process::id(), STATE_DIR_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let config = serde_json::from_value(json!({
+            "api_url": "http://127.0.0.1:1",
+            "interval_analytic_blocks": 1,
+            "message_bus": "in_memory",
+            "state_dir": state_dir.to_str().unwrap(),
+            "rabbitmq_config": {
+                "host": "127.0.0.1",
+                "port": 5552,
+                "username": null,
+                "password": null,
+                "batch_max_latency_ms": 10,
+                "streams": { "partitions": partitions }
+            },
+            "database_config": { "url": "postgresql://127.0.0.1:1/unused" }
+        })).unwrap();
+
+        (config, state_dir)
+    }
+
+    /// Writer БД, который подтверждает любую запись и отдаёт записанные события тесту.
+    fn spawn_acking_writer() -> (Sender<DbWrite>, mpsc::UnboundedReceiver<AnalyticsEvent>) {
+        let (db_sender, mut db_receiver) = mpsc::channel::<DbWrite>(16);
+        let (written_sender, written_receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Some(DbWrite { event, ack }) = db_receiver.recv().await {
+                let _ = ack.send(Ok(()));
+                let _ = written_sender.send(event);
+            }
+        });
+
+        (db_sender, written_receiver)
+    }
+
+    /// Настоящий writer БД поверх тестовой схемы.
+    fn spawn_database_writer(pool: Arc<sqlx::PgPool>) -> Sender<DbWrite> {
+        let (db_sender, mut db_receiver) = mpsc::channel::<DbWrite>(16);
+
+        tokio::spawn(async move {
+            Database::queue_messages_reader(&mut db_receiver, pool, CancellationToken::new()).await;
+        });
+
+        db_sender
+    }
+
+    /// Ждёт, пока подписчик `consumer_name` подтвердит всё, что есть в стриме.
+    async fn wait_until_committed(message_bus: &dyn MessageBus, stream: &str, consumer_name: &str) {
+        timeout(Duration::from_secs(5), async {
+            loop {
+                let mut subscription = message_bus.subscribe(stream, consumer_name).await.unwrap();
+                if timeout(Duration::from_millis(50), subscription.next()).await.is_err() {
+                    break;
                 }
+                sleep(Duration::from_millis(20)).await;
             }
+        }).await.unwrap();
+    }
+
+    async fn spawn_reader(
+        message_bus: Arc<dyn MessageBus>,
+        config: &Config,
+        stream: &str,
+        db_sender: Sender<DbWrite>,
+        shutdown: CancellationToken,
+    ) -> JoinHandle<Result<()>> {
+        let streams = config.get_rabbitmq_config().get_streams();
+        let subscription = message_bus.subscribe(stream, streams.get_consumer_name()).await.unwrap();
+        let dead_letters = DeadLetterQueue::new(message_bus, streams).with_source_stream(stream);
+
+        tokio::spawn(async move {
+            QueueService::read_analytics_messages(subscription, db_sender, &dead_letters, 1, shutdown).await
+        })
+    }
+
+    async fn next_written_height(written: &mut mpsc::UnboundedReceiver<AnalyticsEvent>) -> u32 {
+        match timeout(Duration::from_secs(5), written.recv()).await.unwrap().unwrap() {
+            AnalyticsEvent::Block(message) => message.height,
+            AnalyticsEvent::Reorg(event) => panic!("Unexpected reorg at height {}", event.fork_height),
         }
     }
 
-    // pub async fn shutdown(&mut self) {
-    //     drop(self.sender);
-    //
-    //     if let Some(handle) = self.worker_handle.take() {
-    //         if let Err(e) = handle.await {
-    //             error!("Error waiting for queue worker to shutdown: {:?}", e);
-    //         }
-    //     }
-    // }
+    #[tokio::test]
+    async fn reads_published_blocks_into_the_database_and_commits_offsets() {
+        let (config, state_dir) = test_config(1);
+        let message_bus: Arc<dyn MessageBus> = Arc::new(InMemoryBus::new());
+        let (queue_service, mut queue_worker) = QueueService::new(Arc::clone(&message_bus), &config);
+        let worker_shutdown = CancellationToken::new();
+        let worker = tokio::spawn({
+            let shutdown = worker_shutdown.clone();
+            async move { queue_worker.run(shutdown).await }
+        });
+
+        let live = PublishLane::Live;
+        for height in [800_000, 800_000, 800_001] {
+            let message = block_message(height, &format!("{:064x}", height), &format!("{:064x}", height + 1), "foundry");
+            queue_service.send_block_analytics(message, live.block_target(height as u64)).await.unwrap();
+        }
+
+        let (db_sender, mut written) = spawn_acking_writer();
+        let reader_shutdown = CancellationToken::new();
+        let reader = spawn_reader(Arc::clone(&message_bus), &config, "mining-analytics", db_sender.clone(), reader_shutdown.clone()).await;
+
+        // Повторная отправка блока 800000 отброшена шиной по publishing id.
+        assert_eq!(next_written_height(&mut written).await, 800_000);
+        assert_eq!(next_written_height(&mut written).await, 800_001);
+        assert!(timeout(Duration::from_millis(100), written.recv()).await.is_err());
+
+        reader_shutdown.cancel();
+        reader.await.unwrap().unwrap();
+
+        // Новый читатель продолжает после подтверждённых сообщений.
+        let message = block_message(800_002, &format!("{:064x}", 800_002), &format!("{:064x}", 800_003), "foundry");
+        queue_service.send_block_analytics(message, live.block_target(800_002)).await.unwrap();
+        let reader_shutdown = CancellationToken::new();
+        let reader = spawn_reader(Arc::clone(&message_bus), &config, "mining-analytics", db_sender, reader_shutdown.clone()).await;
+        assert_eq!(next_written_height(&mut written).await, 800_002);
+
+        reader_shutdown.cancel();
+        reader.await.unwrap().unwrap();
+        worker_shutdown.cancel();
+        worker.await.unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(state_dir);
+    }
+
+    #[tokio::test]
+    async fn moves_undecodable_messages_to_the_dead_letter_queue() {
+        let (config, _) = test_config(1);
+        let message_bus: Arc<dyn MessageBus> = Arc::new(InMemoryBus::new());
+
+        let garbage = OutgoingMessage {
+            content_type: Some(Codec::Json.content_type().to_string()),
+            ..OutgoingMessage::new(b"{not json".to_vec())
+        };
+        message_bus.publish("mining-analytics", vec![garbage]).await.unwrap();
+        let event = AnalyticsEvent::Block(block_message(800_000, &format!("{:064x}", 800_000), &format!("{:064x}", 1), "foundry"));
+        let valid = OutgoingMessage {
+            content_type: Some(Codec::Json.content_type().to_string()),
+            ..OutgoingMessage::new(Codec::Json.encode(&Envelope::wrap(&event, "test".to_string(), 0)).unwrap())
+        };
+        message_bus.publish("mining-analytics", vec![valid]).await.unwrap();
+
+        let (db_sender, mut written) = spawn_acking_writer();
+        let reader_shutdown = CancellationToken::new();
+        let reader = spawn_reader(Arc::clone(&message_bus), &config, "mining-analytics", db_sender, reader_shutdown.clone()).await;
+
+        // Битое сообщение не останавливает чтение.
+        assert_eq!(next_written_height(&mut written).await, 800_000);
+        reader_shutdown.cancel();
+        reader.await.unwrap().unwrap();
+
+        let mut dead_letters = message_bus.subscribe("mining-analytics-dlq", "test").await.unwrap();
+        let dead_letter = timeout(Duration::from_secs(5), dead_letters.next()).await.unwrap().unwrap().unwrap();
+        assert_eq!(dead_letter.body, b"{not json");
+        assert_eq!(dead_letter.content_type.as_deref(), Some(Codec::Json.content_type()));
+        assert_eq!(dead_letter.headers["x-dlq-stage"], "decode");
+        assert_eq!(dead_letter.headers["x-dlq-source-stream"], "mining-analytics");
+        assert_eq!(dead_letter.headers["x-dlq-source-offset"], "0");
+
+        // Offset'ы обоих сообщений сохранены: переподписка ничего не читает заново.
+        let mut subscription = message_bus.subscribe("mining-analytics", config.get_rabbitmq_config().get_streams().get_consumer_name()).await.unwrap();
+        assert!(timeout(Duration::from_millis(100), subscription.next()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn saves_an_orphaned_block_that_arrives_after_the_reorg_in_another_partition() {
+        let Some(database) = TestDatabase::connect().await else {
+            return;
+        };
+        let (config, state_dir) = test_config(2);
+        let message_bus: Arc<dyn MessageBus> = Arc::new(InMemoryBus::new());
+        let (queue_service, mut queue_worker) = QueueService::new(Arc::clone(&message_bus), &config);
+        let worker_shutdown = CancellationToken::new();
+        let worker = tokio::spawn({
+            let shutdown = worker_shutdown.clone();
+            async move { queue_worker.run(shutdown).await }
+        });
+
+        // Блок 800001 пула viabtc вытеснен блоком foundry, и они лежат в разных партициях.
+        let orphaned = block_message(800_001, &format!("{:064x}", 0xa1), &format!("{:064x}", 0xa2), "viabtc");
+        let replacement = block_message(800_001, &format!("{:064x}", 0xb1), &format!("{:064x}", 0xb2), "foundry");
+        let block_ref = |message: &BlockAnalyticsMessage| BlockRef {
+            height: message.height as u64,
+            hash: message.block_hash.clone(),
+            guessed_miner: Some(message.coinbase_info.guessed_miner.clone()),
+            pool_slug: message.coinbase_info.pool_slug.clone(),
+        };
+        let reorg_event = ReorgEvent {
+            fork_height: 800_000,
+            fork_hash: format!("{:064x}", 800_000),
+            orphaned_blocks: vec![block_ref(&orphaned)],
+            new_blocks: vec![block_ref(&replacement)],
+            detected_at: 1_700_000_000,
+        };
+
+        let partitions = AnalyticsPartitions::new(config.get_rabbitmq_config().get_streams());
+        let orphaned_stream = partitions.route(&AnalyticsEvent::Block(orphaned.clone()))[0].clone();
+        let replacement_stream = partitions.route(&AnalyticsEvent::Block(replacement.clone()))[0].clone();
+        assert_ne!(orphaned_stream, replacement_stream);
+
+        let reorg_lane = PublishLane::Reorg { producer_name: "mining-analytics-producer-reorg-test".to_string() };
+        queue_service.send_block_analytics(orphaned.clone(), PublishLane::Live.block_target(800_001)).await.unwrap();
+        queue_service.send_reorg_event(reorg_event, reorg_lane.reorg_target(800_000)).await.unwrap();
+        queue_service.send_block_analytics(replacement.clone(), reorg_lane.block_target(800_001)).await.unwrap();
+
+        let consumer_name = config.get_rabbitmq_config().get_streams().get_consumer_name().to_string();
+        let db_sender = spawn_database_writer(Arc::clone(&database.pool));
+        let reader_shutdown = CancellationToken::new();
+
+        // Партиция с новой веткой применяет reorg и пишет блок foundry, пока партиция viabtc ещё не читалась.
+        let replacement_reader = spawn_reader(Arc::clone(&message_bus), &config, &replacement_stream, db_sender.clone(), reader_shutdown.clone()).await;
+        wait_until_committed(&*message_bus, &replacement_stream, &consumer_name).await;
+        let orphaned_reader = spawn_reader(Arc::clone(&message_bus), &config, &orphaned_stream, db_sender, reader_shutdown.clone()).await;
+        wait_until_committed(&*message_bus, &orphaned_stream, &consumer_name).await;
+
+        let blocks: Vec<(String, bool)> = sqlx::query_as("SELECT hash, is_orphaned FROM blocks WHERE height = 800001 ORDER BY hash")
+            .fetch_all(&*database.pool)
+            .await
+            .unwrap();
+        assert_eq!(blocks, vec![(orphaned.block_hash.clone(), true), (replacement.block_hash.clone(), false)]);
+
+        let orphaned_coinbase: bool = sqlx::query_scalar("SELECT is_orphaned FROM transactions WHERE block_hash = $1")
+            .bind(&orphaned.block_hash)
+            .fetch_one(&*database.pool)
+            .await
+            .unwrap();
+        assert!(orphaned_coinbase);
+
+        let mut dead_letters = message_bus.subscribe("mining-analytics-dlq", "test").await.unwrap();
+        assert!(timeout(Duration::from_millis(100), dead_letters.next()).await.is_err());
+
+        reader_shutdown.cancel();
+        replacement_reader.await.unwrap().unwrap();
+        orphaned_reader.await.unwrap().unwrap();
+        worker_shutdown.cancel();
+        worker.await.unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(state_dir);
+        database.drop_schema().await;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,182 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{anyhow, Result};
+use log::error;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Сообщения, которые не удалось отправить в брокер. Append-only JSON Lines: одна строка — одно сообщение.
+/// Файл переписывается целиком только после replay, когда часть сообщений уже ушла.
+/// Строки, которые не разбираются, переносятся в `{path}.rejected`.
+pub struct MessageSpool {
+    path: PathBuf,
+    /// Общий с `TaskRegistry`, чтобы глубину было видно в статусе задач.
+    depth: Arc<AtomicUsize>,
+}
+
+impl MessageSpool {
+    pub fn open(path: PathBuf) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| anyhow!("Couldn't create spool directory {}: {}", dir.display(), e))?;
+        }
+
+        let body = fs::read(&path).unwrap_or_default();
+        let depth = body.split(|byte| *byte == b'\n').filter(|line| !line.is_empty()).count();
+
+        // Падение во время `append` оставляет последнюю строку оборванной: завершаем её,
+        // чтобы следующая запись не склеилась с ней. Саму строку отсеет `read_all`.
+        if body.last().is_some_and(|byte| *byte != b'\n') {
+            OpenOptions::new().append(true).open(&path)
+                .and_then(|mut file| file.write_all(b"\n"))
+                .map_err(|e| anyhow!("Couldn't repair spool {}: {}", path.display(), e))?;
+        }
+
+        Ok(Self { path, depth: Arc::new(AtomicUsize::new(depth)) })
+    }
+
+    /// Сколько сообщений ждёт отправки.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    pub fn depth_gauge(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.depth)
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn append<T: Serialize>(&mut self, messages: &[T]) -> Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+
+        let mut body = Vec::new();
+        for message in messages.iter() {
+            serde_json::to_writer(&mut body, message)?;
+            body.push(b'\n');
+        }
+
+        file.write_all(&body)?;
+        file.sync_data()?;
+        self.depth.fetch_add(messages.len(), Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Битые строки не останавливают чтение: они переносятся в `{path}.rejected`, а spool переписывается без них,
+    /// иначе одна оборванная строка навсегда заблокировала бы все сообщения за ней.
+    pub fn read_all<T: Serialize + DeserializeOwned>(&mut self) -> Result<Vec<T>> {
+        let body = match fs::read(&self.path) {
+            Ok(body) => body,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        let mut messages = Vec::new();
+        let mut rejected = Vec::new();
+        for line in body.split(|byte| *byte == b'\n') {
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_slice(line) {
+                Ok(message) => messages.push(message),
+                Err(e) => {
+                    error!("Bad spooled message in {}, moving it to {}: {}", self.path.display(), self.rejected_path().display(), e);
+                    rejected.push(line);
+                }
+            }
+        }
+
+        if !rejected.is_empty() {
+            let mut file = OpenOptions::new().create(true).append(true).open(self.rejected_path())?;
+            for line in rejected {
+                file.write_all(line)?;
+                file.write_all(b"\n")?;
+            }
+            file.sync_data()?;
+            self.replace(&messages)?;
+        }
+
+        Ok(messages)
+    }
+
+    fn rejected_path(&self) -> PathBuf {
+        let mut rejected_path = self.path.clone().into_os_string();
+        rejected_path.push(".rejected");
+        PathBuf::from(rejected_path)
+    }
+
+    /// Оставляет в spool только `remaining` (через временный файл, как `JsonStateStore`).
+    pub fn replace<T: Serialize>(&mut self, remaining: &[T]) -> Result<()> {
+        if remaining.is_empty() {
+            if self.path.exists() {
+                fs::remove_file(&self.path)?;
+            }
+            self.depth.store(0, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let mut body = Vec::new();
+        for message in remaining.iter() {
+            serde_json::to_writer(&mut body, message)?;
+            body.push(b'\n');
+        }
+
+        fs::write(&tmp_path, body)?;
+        fs::rename(&tmp_path, &self.path)?;
+        self.depth.store(remaining.len(), Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    static SPOOL_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn spool_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("spool_test_{}_{}", std::process::id(), SPOOL_COUNTER.fetch_add(1, Ordering::Relaxed)))
+            .join("analytics_spool.jsonl")
+    }
+
+    #[test]
+    fn skips_a_truncated_trailing_line() {
+        let path = spool_path();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        // Падение посреди `append`: последняя строка оборвана и без перевода строки.
+        fs::write(&path, "{\"height\":1}\n{\"height\":2}\n{\"heig").unwrap();
+
+        let mut spool = MessageSpool::open(path.clone()).unwrap();
+        spool.append(&[json!({"height": 3})]).unwrap();
+
+        let messages: Vec<Value> = spool.read_all().unwrap();
+        assert_eq!(messages, vec![json!({"height": 1}), json!({"height": 2}), json!({"height": 3})]);
+        assert_eq!(spool.depth(), 3);
+        assert_eq!(fs::read_to_string(spool.rejected_path()).unwrap(), "{\"heig\n");
+
+        // Spool переписан без битой строки: повторное чтение ничего не переносит.
+        let messages: Vec<Value> = spool.read_all().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(fs::read_to_string(spool.rejected_path()).unwrap(), "{\"heig\n");
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
\ No newline at end of file
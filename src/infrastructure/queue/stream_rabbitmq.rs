@@ -1,22 +1,40 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
 
-use log::{error, info};
+use log::{info, warn};
 
 use tokio::sync::Mutex;
+use tokio::time::timeout;
 
-use rabbitmq_stream_client::{Dedup, Environment, Producer};
-use rabbitmq_stream_client::types::{ByteCapacity, Message, ResponseCode};
-use rabbitmq_stream_client::error::StreamCreateError;
+use rabbitmq_stream_client::{Consumer, Dedup, Environment, NoDedup, Producer};
+use rabbitmq_stream_client::types::{ByteCapacity, Message, OffsetSpecification, ResponseCode, SimpleValue};
+use rabbitmq_stream_client::error::{ClientError, ConsumerStoreOffsetError, StreamCreateError};
 
-use crate::config::config::RabbitMqConfig;
+use crate::config::config::{RabbitMqConfig, StreamsConfig};
+use crate::infrastructure::queue::partitioning::AnalyticsPartitions;
+use crate::infrastructure::queue::message_bus::{Delivery, MessageBus, OutgoingMessage, Subscription};
+
+/// Брокер дедуплицирует только именованные producer'ы, а у клиента это разные типы.
+enum StreamProducer {
+    Named(Producer<Dedup>),
+    Unnamed(Producer<NoDedup>),
+}
+
+type SharedProducer = Arc<Mutex<StreamProducer>>;
+
+/// Сколько ждём подтверждения батча от брокера, прежде чем считать отправку неудачной.
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[allow(dead_code)]
 pub struct RabbitMQClient {
     environment: Arc<Environment>,
-    block_analytics_producer: Arc<Mutex<Producer<Dedup>>>,
-    stream_name: String,
+    /// Producer'ы по (стрим, имя producer'а); `None` — producer без имени.
+    producers: Mutex<HashMap<(String, Option<String>), SharedProducer>>,
     host: String,
     port: u16,
     username: Option<String>,
@@ -25,12 +43,6 @@ pub struct RabbitMQClient {
 
 impl RabbitMQClient {
     pub async fn new(config: &RabbitMqConfig) -> Result<Self> {
-        let block_analytics_stream = "mining-analytics";
-        let mining_notifications_stream = "mining-notifications";
-
-        let mining_analytics_producer_name = "mining-analytics-producer";
-        // let mining_notifications_producer_name = "mining-notifications-producer";
-
         let environment = Arc::new(
             Environment::builder()
                 .host(config.get_host())
@@ -44,81 +56,238 @@ impl RabbitMQClient {
         );
 
         // Сначала создаем стримы
-        RabbitMQClient::create_stream(&environment, block_analytics_stream).await;
-        RabbitMQClient::create_stream(&environment, mining_notifications_stream).await;
-
-        // Потом создаем producer
-        let analytics_block_producer = Arc::new(Mutex::new(environment
-            .producer()
-            .name(mining_analytics_producer_name)
-            .build(block_analytics_stream)
-            .await?));
-
-        info!("RabbitMq client initialized successfully");
+        let streams = config.get_streams();
+        let analytics_partitions = AnalyticsPartitions::new(streams);
+        for partition in analytics_partitions.streams() {
+            RabbitMQClient::create_stream(&environment, partition, streams).await;
+        }
+        RabbitMQClient::create_stream(&environment, streams.get_notifications(), streams).await;
+        RabbitMQClient::create_stream(&environment, &streams.get_dead_letter(), streams).await;
 
-        Ok(Self {
+        let client = Self {
             environment: Arc::clone(&environment),
-            block_analytics_producer: analytics_block_producer,
-            stream_name: config.get_stream_name().to_string(),
+            producers: Mutex::new(HashMap::new()),
             host: config.get_host().to_string(),
             port: config.get_port(),
             password: config.get_password().cloned(),
             username: config.get_password().cloned()
-        })
+        };
+
+        // Producer'ы по умолчанию создаём сразу, чтобы недоступный брокер был виден при старте.
+        for partition in analytics_partitions.streams() {
+            client.producer(partition, Some(&streams.get_producer_name())).await?;
+        }
+        client.producer(streams.get_notifications(), None).await?;
+
+        info!("RabbitMq client initialized successfully");
+
+        Ok(client)
     }
 
-    pub async fn send_to_stream<T: serde::Serialize>(&self, data: &Vec<T>) -> Result<()> {
-        let messages: Vec<Message> = data.into_iter()
-            .map(|data| {
-                let json_bytes = serde_json::to_vec(&data).unwrap();
-                Message::builder().body(json_bytes).build()
-            })
-            .collect();
+    /// `batch_send` возвращает `Ok` ещё до ответа брокера, поэтому ждём подтверждение каждого сообщения:
+    /// иначе повторы, spool и replay DLQ считали бы неотправленные сообщения отправленными.
+    async fn send_to_stream(producer: &Mutex<StreamProducer>, messages: Vec<Message>) -> Result<()> {
+        let count = messages.len();
+        let mut producer = producer.lock().await;
 
-        self.block_analytics_producer.lock().await.batch_send(messages, |res| async move {
-            match res {
-                Ok(confirmation_status) => {
-                    let status = confirmation_status.status();
-                    match status {
-                        ResponseCode::Ok => info!("Messages send successfully!"),
-                        other_response => {
-                            error!("Couldn't send messages, response with status: {:?}", other_response);
-                        }
-                    }
-                },
-                Err(e) => error!("Batch send with error: {:?}", e),
-            }
-        }).await?;
+        let confirmations = match &mut *producer {
+            StreamProducer::Named(producer) => timeout(CONFIRMATION_TIMEOUT, producer.batch_send_with_confirm(messages)).await,
+            StreamProducer::Unnamed(producer) => timeout(CONFIRMATION_TIMEOUT, producer.batch_send_with_confirm(messages)).await,
+        }
+            .map_err(|_| anyhow!("No confirmation for {} messages within {:?}", count, CONFIRMATION_TIMEOUT))??;
+
+        if confirmations.len() < count {
+            return Err(anyhow!("Only {} of {} messages were confirmed", confirmations.len(), count));
+        }
 
+        let rejected: Vec<_> = confirmations.iter()
+            .filter(|confirmation| !confirmation.confirmed() || !matches!(confirmation.status(), ResponseCode::Ok))
+            .collect();
+        if let Some(first_rejected) = rejected.first() {
+            return Err(anyhow!(
+                "{} of {} messages weren't confirmed, first publishing id {} with status {:?}",
+                rejected.len(), count, first_rejected.publishing_id(), first_rejected.status()
+            ));
+        }
+
+        info!("{} messages confirmed", count);
         Ok(())
     }
 
+    /// Producer'ы создаются при первой отправке.
+    async fn producer(&self, stream: &str, producer_name: Option<&str>) -> Result<SharedProducer> {
+        let key = (stream.to_string(), producer_name.map(str::to_string));
+
+        let mut producers = self.producers.lock().await;
+        if let Some(producer) = producers.get(&key) {
+            return Ok(Arc::clone(producer));
+        }
+
+        let producer = match producer_name {
+            Some(producer_name) => {
+                let producer = self.environment.producer().name(producer_name).build(stream).await?;
+                info!("Producer {} created for stream {}", producer_name, stream);
+                StreamProducer::Named(producer)
+            }
+            None => {
+                let producer = self.environment.producer().build(stream).await?;
+                info!("Producer without deduplication created for stream {}", stream);
+                StreamProducer::Unnamed(producer)
+            }
+        };
+        let producer = Arc::new(Mutex::new(producer));
 
-    pub async fn send_batch_analytics_messages<T: serde::Serialize>(&self, data: &Vec<T>) -> Result<()> {
-        self.send_to_stream(data).await
+        producers.insert(key, Arc::clone(&producer));
+        Ok(producer)
     }
 
-    pub fn get_environment(&self) -> Arc<Environment> {
-        Arc::clone(&self.environment)
+    /// Offset хранится на сервере под именем consumer'а. Узнать его можно только через consumer,
+    /// поэтому сначала открываем временный, спрашиваем offset и закрываем.
+    async fn resume_offset(&self, stream: &str, subscriber: &str) -> Result<OffsetSpecification> {
+        let probe_consumer = self.environment
+            .consumer()
+            .name(subscriber)
+            .offset(OffsetSpecification::Next)
+            .build(stream)
+            .await?;
+
+        let stored_offset = probe_consumer.query_offset().await;
+        if let Err(err) = probe_consumer.handle().close().await {
+            warn!("Couldn't close offset probe consumer: {:?}", err);
+        }
+
+        match stored_offset {
+            Ok(offset) => {
+                info!("Resuming {} from offset {}", subscriber, offset + 1);
+                Ok(OffsetSpecification::Offset(offset + 1))
+            }
+            // С начала читаем только если offset действительно не сохранялся: на прочих ошибках
+            // откат на First переиграл бы весь стрим.
+            Err(ConsumerStoreOffsetError::Client(ClientError::RequestError(ResponseCode::OffsetNotFound))) => {
+                info!("No stored offset for {}, reading {} from the beginning", subscriber, stream);
+                Ok(OffsetSpecification::First)
+            }
+            Err(err) => Err(anyhow::anyhow!("Couldn't query stored offset of {} on {}: {:?}", subscriber, stream, err)),
+        }
     }
 
-    pub async fn create_stream(environment: &Environment, consumer_name: &str) {
-        let create_response = environment
-            .stream_creator()
-            .max_length(ByteCapacity::GB(5))
-            .create(consumer_name)
-            .await;
-
-        if let Err(e) = create_response {
-            if let StreamCreateError::Create { stream, status } = e {
-                match status {
-                    // we can ignore this error because the stream already exists
-                    ResponseCode::StreamAlreadyExists => {}
-                    err => {
-                        println!("Error creating stream: {:?} {:?}", stream, err);
-                    }
+    /// Retention задаётся только при создании: если стрим уже есть, его настройки не меняются.
+    pub async fn create_stream(environment: &Environment, stream_name: &str, streams: &StreamsConfig) {
+        let mut stream_creator = environment.stream_creator();
+        if let Some(max_length_bytes) = streams.get_max_length_bytes() {
+            stream_creator = stream_creator.max_length(ByteCapacity::B(max_length_bytes));
+        }
+        if let Some(max_age_secs) = streams.get_max_age_secs() {
+            stream_creator = stream_creator.max_age(Duration::from_secs(max_age_secs));
+        }
+        if let Some(max_segment_size_bytes) = streams.get_max_segment_size_bytes() {
+            stream_creator = stream_creator.max_segment_size(ByteCapacity::B(max_segment_size_bytes));
+        }
+
+        let create_response = stream_creator.create(stream_name).await;
+
+        if let Err(StreamCreateError::Create { stream, status }) = create_response {
+            match status {
+                // we can ignore this error because the stream already exists
+                ResponseCode::StreamAlreadyExists => {}
+                err => {
+                    println!("Error creating stream: {:?} {:?}", stream, err);
                 }
             }
         }
     }
+}
+
+#[async_trait]
+impl MessageBus for RabbitMQClient {
+    fn name(&self) -> &'static str {
+        "rabbitmq-streams"
+    }
+
+    /// Подряд идущие сообщения одного producer'а уходят одним batch'ем.
+    async fn publish(&self, stream: &str, messages: Vec<OutgoingMessage>) -> Result<()> {
+        for producer_messages in messages.chunk_by(|left, right| left.producer == right.producer) {
+            let producer = self.producer(stream, producer_messages[0].producer.as_deref()).await?;
+
+            let messages = producer_messages.iter()
+                .map(|message| {
+                    let builder = Message::builder();
+                    // Опечатка в названии метода — из rabbitmq-stream-client.
+                    let builder = match message.publishing_id {
+                        Some(publishing_id) => builder.publising_id(publishing_id),
+                        None => builder,
+                    };
+                    let builder = match &message.content_type {
+                        Some(content_type) => builder.properties().content_type(content_type.as_str()).message_builder(),
+                        None => builder,
+                    };
+                    let builder = if message.headers.is_empty() {
+                        builder
+                    } else {
+                        message.headers.iter()
+                            .fold(builder.application_properties(), |properties, (key, value)| properties.insert(key.as_str(), value.as_str()))
+                            .message_builder()
+                    };
+                    builder.body(message.body.clone()).build()
+                })
+                .collect();
+
+            Self::send_to_stream(&producer, messages).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn subscribe(&self, stream: &str, subscriber: &str) -> Result<Box<dyn Subscription>> {
+        let offset_specification = self.resume_offset(stream, subscriber).await?;
+        let consumer = self.environment
+            .consumer()
+            .name(subscriber)
+            .offset(offset_specification)
+            .build(stream)
+            .await?;
+
+        Ok(Box::new(RabbitMqSubscription { consumer }))
+    }
+}
+
+struct RabbitMqSubscription {
+    consumer: Consumer,
+}
+
+#[async_trait]
+impl Subscription for RabbitMqSubscription {
+    async fn next(&mut self) -> Result<Option<Delivery>> {
+        let Some(delivery) = self.consumer.try_next().await.map_err(|err| anyhow::anyhow!("{:?}", err))? else {
+            return Ok(None);
+        };
+
+        let message = delivery.message();
+        Ok(Some(Delivery {
+            offset: delivery.offset(),
+            body: message.data().map(<[u8]>::to_vec).unwrap_or_default(),
+            content_type: message.properties()
+                .and_then(|properties| properties.content_type.as_ref())
+                .map(|content_type| content_type.to_string()),
+            headers: message.application_properties()
+                .map(|properties| properties.iter()
+                    .filter_map(|(key, value)| match value {
+                        SimpleValue::String(value) => Some((key.to_string(), value.to_string())),
+                        SimpleValue::Symbol(value) => Some((key.to_string(), value.to_string())),
+                        _ => None,
+                    })
+                    .collect())
+                .unwrap_or_default(),
+        }))
+    }
+
+    async fn commit(&mut self, offset: u64) -> Result<()> {
+        self.consumer.store_offset(offset).await.map_err(|err| anyhow::anyhow!("{:?}", err))
+    }
+
+    async fn close(self: Box<Self>) {
+        if let Err(err) = self.consumer.handle().close().await {
+            warn!("Couldn't close consumer: {:?}", err);
+        }
+    }
 }
\ No newline at end of file
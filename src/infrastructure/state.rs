@@ -0,0 +1 @@
+pub mod json_store;
\ No newline at end of file
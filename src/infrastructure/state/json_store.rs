@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Хранилище локального состояния сервиса (прогресс backfill, последний обработанный блок и т.п.).
+/// Каждое значение лежит в отдельном `<dir>/<name>.json`.
+#[derive(Debug, Clone)]
+pub struct JsonStateStore {
+    dir: PathBuf,
+}
+
+impl JsonStateStore {
+    pub fn new(dir: &str) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .map_err(|e| anyhow!("Couldn't create state directory {}: {}", dir, e))?;
+
+        Ok(Self { dir: PathBuf::from(dir) })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.json"))
+    }
+
+    pub fn load<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>> {
+        let path = self.path(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let body = fs::read_to_string(&path)?;
+        let value = serde_json::from_str(&body)
+            .map_err(|e| anyhow!("Couldn't parse state {}: {}", path.display(), e))?;
+
+        Ok(Some(value))
+    }
+
+    /// Пишем во временный файл и переименовываем, чтобы при падении не остался обрезанный JSON.
+    pub fn save<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        let path = self.path(name);
+        let tmp_path = self.dir.join(format!("{name}.json.tmp"));
+
+        let body = serde_json::to_vec_pretty(value)?;
+        fs::write(&tmp_path, body)?;
+        fs::rename(&tmp_path, &path)?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
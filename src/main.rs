@@ -5,7 +5,9 @@ mod domain;
 mod utils;
 mod scheduler;
 mod logs;
+mod cli;
 
+use std::env;
 use std::sync::Arc;
 
 use log::error;
@@ -14,10 +16,12 @@ use reqwest::Client;
 
 use tracing::info;
 
+use crate::cli::command::Command;
 use crate::config::config::Config;
+use crate::infrastructure::collector::chain_source::build_chain_source;
 use crate::infrastructure::db::postgres::Database;
 use crate::infrastructure::queue::queue_service::QueueService;
-use crate::infrastructure::queue::stream_rabbitmq::RabbitMQClient;
+use crate::infrastructure::queue::message_bus::build_message_bus;
 use crate::logs::init_logs::init_tracing;
 use crate::scheduler::SchedulerManager;
 
@@ -29,30 +33,55 @@ async fn main() {
     let config = Arc::new(Config::new());
     info!("Config: {:?}", config);
 
-    let rabbit_mq_client = RabbitMQClient::new(config.get_rabbitmq_config())
+    let command = match Command::from_args(&env::args().collect::<Vec<_>>()) {
+        Ok(command) => command,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if command != Command::Run {
+        if let Err(e) = command.run_dead_letter_command(&config).await {
+            error!("Command {:?} failed: {:#}", command, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let message_bus = build_message_bus(&config)
         .await
-        .inspect_err(|e| error!("Error creating RabbitMq client: {}", e))
+        .inspect_err(|e| error!("Error creating message bus: {}", e))
         .ok();
 
     let db = Database::new(config.get_database_url()).await.ok();
 
-    let queue_service = match rabbit_mq_client {
+    let queue = match message_bus {
         None => {
-            info!("RabbitMQ client creation failed, continuing without queue service.");
+            info!("Message bus creation failed, continuing without queue service.");
             None
         }
-        Some(rabbitmq) => {
-            info!("RabbitMQ client created successfully.");
-            Some(Arc::new(QueueService::new(Arc::new(rabbitmq))))
+        Some(message_bus) => {
+            info!("Message bus {} created successfully.", message_bus.name());
+            let (queue_service, queue_worker) = QueueService::new(message_bus, &config);
+            Some((Arc::new(queue_service), queue_worker))
         }
     };
 
 
-    let client_for_scheduler = Arc::clone(&reqwest_client);
+    let chain_source = match build_chain_source(&config, Arc::clone(&reqwest_client)) {
+        Ok(chain_source) => chain_source,
+        Err(e) => {
+            error!("Error creating chain source: {}", e);
+            return;
+        }
+    };
+    info!("Chain source: {}", chain_source.name());
+
     let config_for_scheduler = Arc::clone(&config);
 
-    let mut scheduler = SchedulerManager::new(config_for_scheduler, client_for_scheduler);
+    let mut scheduler = SchedulerManager::new(config_for_scheduler, chain_source);
 
-    scheduler.launch_all_tasks(queue_service, db).await;
-    scheduler.wait_for_all_tasks().await;
+    scheduler.launch_all_tasks(queue, db);
+    scheduler.run_until_shutdown().await;
 }
\ No newline at end of file
@@ -1,77 +1,323 @@
+use std::future::Future;
 use std::sync::Arc;
-use log::error;
-use reqwest::Client;
-use tokio::sync::mpsc::Receiver;
-use tokio::task::JoinHandle;
-use crate::config::config::Config;
-use crate::infrastructure::db::postgres::Database;
-use crate::infrastructure::queue::queue_service::{BlockAnalyticsMessage, QueueService};
+use std::time::Duration;
+use futures_util::future::join_all;
+use log::{error, info, warn};
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::task::{JoinError, JoinHandle};
+use tokio_util::sync::CancellationToken;
+use crate::application::notifications::NotificationRules;
+use crate::config::config::{Config, PipelineTopology};
+use crate::infrastructure::collector::chain_source::ChainSource;
+use crate::infrastructure::db::postgres::{Database, DbWrite};
+use crate::infrastructure::queue::partitioning::AnalyticsPartitions;
+use crate::infrastructure::queue::queue_service::{QueueService, QueueWorker};
+use crate::infrastructure::state::json_store::JsonStateStore;
+use crate::scheduler::backfill::BackfillJob;
+use crate::utils::pool_identifier::PoolIdentifier;
 use crate::scheduler::block_watcher::BlockWatcher;
 use crate::scheduler::rabbit_watcher::MessageIngestionService;
+use crate::scheduler::shutdown::ShutdownStage;
+use crate::scheduler::supervisor::{Supervisor, TaskRegistry};
 
 pub mod block_watcher;
 mod rabbit_watcher;
+mod backfill;
+mod shutdown;
+mod supervisor;
 
 pub struct SchedulerManager {
-    tasks: Vec<JoinHandle<()>>,
+    tasks: Vec<(ShutdownStage, JoinHandle<()>)>,
+    /// По токену на каждую стадию, индекс — `ShutdownStage as usize`.
+    shutdown_tokens: [CancellationToken; ShutdownStage::ORDER.len()],
     config: Arc<Config>,
-    client: Arc<Client>
+    chain_source: Arc<dyn ChainSource>,
+    supervisor: Supervisor,
 }
 
 impl SchedulerManager {
-    pub fn new(config: Arc<Config>, client: Arc<Client>) -> Self {
+    pub fn new(config: Arc<Config>, chain_source: Arc<dyn ChainSource>) -> Self {
+        let supervisor = Supervisor::new(config.get_supervisor_config().clone());
+
         SchedulerManager {
             tasks: Vec::new(),
+            shutdown_tokens: Default::default(),
             config,
-            client
+            chain_source,
+            supervisor,
         }
     }
 
-    pub async fn launch_all_tasks(&mut self, queue_service: Option<Arc<QueueService>>, db: Option<(Arc<Database>, Receiver<BlockAnalyticsMessage>)>) {
-        let client_for_block_watcher = Arc::clone(&self.client);
+    /// Каждый долгоживущий компонент запускается под супервизором; состояние задач — в `TaskRegistry`.
+    pub fn launch_all_tasks(&mut self, queue: Option<(Arc<QueueService>, QueueWorker)>, db: Option<(Arc<Database>, Receiver<DbWrite>)>) {
+        let (queue_service, queue_worker) = queue.unzip();
+        if let Some(queue_worker) = queue_worker {
+            self.launch_queue_worker_task(queue_worker);
+        }
+
+        let chain_source_for_block_watcher = Arc::clone(&self.chain_source);
         let config_for_block_watcher = Arc::clone(&self.config);
 
         let config_for_rabbit_watcher = Arc::clone(&self.config);
 
         let queue_service_for_block = queue_service.as_ref().map(Arc::clone);
+        let queue_service_for_backfill = queue_service.as_ref().map(Arc::clone);
         let queue_service_for_rabbit = queue_service.as_ref().map(Arc::clone);
 
-        let mut block_watcher = BlockWatcher::new(client_for_block_watcher, config_for_block_watcher, queue_service_for_block);
-        let mut message_ingestion_service = MessageIngestionService::new(config_for_rabbit_watcher, queue_service_for_rabbit);
-        let block_watcher_task = tokio::spawn(async move {
-            let block_watcher_result = block_watcher.start_monitoring_new_blocks().await;
+        let state_store = JsonStateStore::new(self.config.get_state_dir())
+            .inspect_err(|err| error!("State store error, progress won't be persisted: {}", err))
+            .ok();
 
-            if let Err(err) = block_watcher_result {
-                error!("Scheduler Manager Error: {}", err.to_string());
-            }
+        let pool_identifier = Arc::new(self.load_pool_identifier());
+
+        let pipeline = self.resolve_pipeline(queue_service.is_some(), db.is_some());
+        let db_sender = db.as_ref().map(|(db, _)| db.sender.clone());
+
+        let notifications_config = self.config.get_notifications_config();
+        let notification_rules = notifications_config.is_enabled()
+            .then(|| NotificationRules::new(notifications_config.clone()));
+
+        let block_watcher = BlockWatcher::new(
+            chain_source_for_block_watcher,
+            config_for_block_watcher,
+            queue_service_for_block,
+            state_store.clone(),
+            Arc::clone(&pool_identifier),
+            notification_rules,
+        ).with_pipeline(pipeline, db_sender.clone());
+        let block_watcher = Arc::new(Mutex::new(block_watcher));
+        self.spawn_supervised(ShutdownStage::Sources, "block-watcher", move |shutdown| {
+            let block_watcher = Arc::clone(&block_watcher);
+            async move { block_watcher.lock().await.start_monitoring_new_blocks(shutdown).await }
         });
 
+        self.launch_backfill_task(queue_service_for_backfill, state_store, pool_identifier, pipeline, db_sender.clone());
 
-        // let _ = tokio::spawn(async move {
-        //     db.queue_messages_reader().await;
-        // });
+        let db_pool = db.as_ref().map(|(db, _)| db.pool());
         if let Some((db, db_receiver)) = db {
-            let db_sender = db.sender.clone();
+            self.launch_db_writer_task(db_receiver, db.pool());
+        }
+
+        // При `both` watcher уже пишет в БД сам, читать mining-analytics обратно незачем.
+        if pipeline == PipelineTopology::Queue
+            && let (Some(db_sender), Some(db_pool)) = (db_sender, db_pool) {
+            self.launch_ingestion_tasks(config_for_rabbit_watcher, queue_service_for_rabbit, db_sender, db_pool);
+        }
+    }
+
+    /// По задаче на партицию: упавшая партиция перезапускается, не останавливая остальные.
+    fn launch_ingestion_tasks(&mut self, config: Arc<Config>, queue_service: Option<Arc<QueueService>>, db_sender: Sender<DbWrite>, db_pool: Arc<sqlx::PgPool>) {
+        let streams = config.get_rabbitmq_config().get_streams();
+        let partitions = AnalyticsPartitions::new(streams);
+
+        let mut message_ingestion_service = MessageIngestionService::new(Arc::clone(&config), queue_service.map(|queue_service| queue_service.message_bus()));
+        if streams.is_single_active_consumer() {
+            message_ingestion_service = message_ingestion_service.with_single_active_consumer(db_pool);
+        }
+        let message_ingestion_service = Arc::new(message_ingestion_service);
+
+        let single_stream = partitions.streams().len() == 1;
+        for (partition, stream) in partitions.streams().iter().enumerate() {
+            let task_name = if single_stream {
+                "analytics-ingestion".to_string()
+            } else {
+                format!("analytics-ingestion-{}", partition)
+            };
+
+            let message_ingestion_service = Arc::clone(&message_ingestion_service);
+            let db_sender = db_sender.clone();
+            let stream = stream.clone();
+            self.spawn_supervised(ShutdownStage::Queue, &task_name, move |shutdown| {
+                let message_ingestion_service = Arc::clone(&message_ingestion_service);
+                let db_sender = db_sender.clone();
+                let stream = stream.clone();
+                async move { message_ingestion_service.start_monitoring_rabbit_messages(&stream, db_sender, shutdown).await }
+            });
+        }
+    }
+
+    /// Без брокера топология `queue` ничего не сохранила бы, поэтому при доступной БД переключаемся на прямую запись.
+    fn resolve_pipeline(&self, queue_available: bool, database_available: bool) -> PipelineTopology {
+        let pipeline = self.config.get_pipeline();
+
+        if pipeline == PipelineTopology::Queue && !queue_available && database_available {
+            warn!("RabbitMQ is unavailable, block analytics will be written directly to the database");
+            return PipelineTopology::Direct;
+        }
+
+        if pipeline.writes_to_database() && !database_available {
+            error!("Pipeline {:?} writes to the database, but it's unavailable: block analytics won't be saved there", pipeline);
+        }
+
+        info!("Pipeline topology: {:?}", pipeline);
+        pipeline
+    }
+
+    fn spawn_supervised<F, Fut>(&mut self, stage: ShutdownStage, name: &str, run: F)
+    where
+        F: Fn(CancellationToken) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let shutdown = self.shutdown_tokens[stage as usize].clone();
+        let task = self.supervisor.spawn(name, shutdown, run);
+
+        self.tasks.push((stage, task));
+    }
+
+    fn launch_queue_worker_task(&mut self, queue_worker: QueueWorker) {
+        if let Some(spool_depth) = queue_worker.spool_depth_gauge() {
+            self.supervisor.registry().register_gauge("analytics-spool-depth", spool_depth);
+        }
+
+        let queue_worker = Arc::new(Mutex::new(queue_worker));
+
+        self.spawn_supervised(ShutdownStage::Queue, "queue-worker", move |shutdown| {
+            let queue_worker = Arc::clone(&queue_worker);
+            async move { queue_worker.lock().await.run(shutdown).await }
+        });
+    }
 
-            let message_ingestion_service_result = message_ingestion_service.start_monitoring_rabbit_messages(db_sender, db_receiver, db.pool()).await;
+    fn launch_db_writer_task(&mut self, db_receiver: Receiver<DbWrite>, db_pool: Arc<sqlx::PgPool>) {
+        let db_receiver = Arc::new(Mutex::new(db_receiver));
 
-            if let Err(err) = message_ingestion_service_result {
-                error!("Scheduler Manager Error: {}", err.to_string());
+        self.spawn_supervised(ShutdownStage::Storage, "db-writer", move |shutdown| {
+            let db_receiver = Arc::clone(&db_receiver);
+            let db_pool = Arc::clone(&db_pool);
+            async move {
+                Database::queue_messages_reader(&mut *db_receiver.lock().await, Arc::clone(&db_pool), shutdown.clone()).await;
+
+                if shutdown.is_cancelled() {
+                    db_pool.close().await;
+                }
+                Ok(())
+            }
+        });
+    }
+
+    fn load_pool_identifier(&self) -> PoolIdentifier {
+        let Some(pools_file) = self.config.get_pools_file() else {
+            info!("Pools file isn't configured, every miner will be reported as unknown");
+            return PoolIdentifier::empty();
+        };
+
+        match PoolIdentifier::from_file(pools_file) {
+            Ok(pool_identifier) => {
+                info!("Loaded {} pool definitions from {}", pool_identifier.pools_count(), pools_file);
+                pool_identifier
+            }
+            Err(err) => {
+                error!("Pool identification disabled: {}", err);
+                PoolIdentifier::empty()
+            }
+        }
+    }
+
+    fn launch_backfill_task(
+        &mut self,
+        queue_service: Option<Arc<QueueService>>,
+        state_store: Option<JsonStateStore>,
+        pool_identifier: Arc<PoolIdentifier>,
+        pipeline: PipelineTopology,
+        db_sender: Option<Sender<DbWrite>>,
+    ) {
+        let Some(backfill_config) = self.config.get_backfill_config().filter(|backfill| backfill.is_enabled()) else {
+            return;
+        };
+
+        let Some(state_store) = state_store else {
+            error!("Backfill disabled: it can't record progress without a state store");
+            return;
+        };
+
+        // Backfill не двигает вершину live-watcher'а и не шлёт уведомлений, поэтому ни state store, ни правил ему не передаём.
+        let block_watcher = BlockWatcher::new(Arc::clone(&self.chain_source), Arc::clone(&self.config), queue_service, None, pool_identifier, None)
+            .with_pipeline(pipeline, db_sender);
+        let analytics_stream = self.config.get_rabbitmq_config().get_streams().get_analytics();
+        let backfill_job = BackfillJob::new(Arc::clone(&self.chain_source), backfill_config.clone(), block_watcher, state_store, analytics_stream);
+
+        // Прогресс backfill'а сохраняется в state store, так что после перезапуска он продолжит с того же места.
+        let backfill_job = Arc::new(Mutex::new(backfill_job));
+        self.spawn_supervised(ShutdownStage::Sources, "backfill", move |shutdown| {
+            let backfill_job = Arc::clone(&backfill_job);
+            async move { backfill_job.lock().await.run(shutdown).await }
+        });
+    }
+
+    /// Работает, пока не завершатся все задачи или не придёт SIGINT/SIGTERM.
+    /// По сигналу останавливает задачи по стадиям и ждёт их не дольше `shutdown_timeout_secs`.
+    pub async fn run_until_shutdown(&mut self) {
+        let status_reporter = Self::spawn_status_reporter(
+            self.supervisor.registry(),
+            Duration::from_secs(self.config.get_supervisor_config().get_status_log_interval_secs().max(1)),
+        );
+
+        let signal = tokio::select! {
+            results = join_all(self.tasks.iter_mut().map(|(_, task)| task)) => {
+                results.into_iter().for_each(Self::log_join_result);
+                info!("All tasks finished");
+                None
+            }
+            signal = shutdown::wait_for_signal() => Some(signal),
+        };
+
+        if let Some(signal) = signal {
+            let deadline = Duration::from_secs(self.config.get_shutdown_timeout_secs());
+            info!("Received {}, shutting down (deadline {:?})", signal, deadline);
+
+            match tokio::time::timeout(deadline, self.shutdown()).await {
+                Ok(()) => info!("Shutdown completed"),
+                Err(_) => error!("Shutdown didn't finish within {:?}, exiting anyway", deadline),
             }
-            // self.tasks.push(rabbit_watcher_task);
         }
 
-        self.tasks.push(block_watcher_task);
+        status_reporter.abort();
+        Self::log_task_statuses(&self.supervisor.registry());
     }
 
-    pub async fn wait_for_all_tasks(&mut self) {
-        for task in self.tasks.drain(..) {
-            match task.await {
-                Ok(()) => {}
-                Err(e) if e.is_cancelled() => error!("Task was cancelled"),
-                Err(e) if e.is_panic() => error!("Task panicked: {e:?}"),
-                Err(e) => error!("Join error: {e:?}"),
+    async fn shutdown(&mut self) {
+        for stage in ShutdownStage::ORDER {
+            info!("Stopping {:?} tasks", stage);
+            self.shutdown_tokens[stage as usize].cancel();
+
+            for (_, task) in self.tasks.iter_mut().filter(|(task_stage, _)| *task_stage == stage) {
+                // Завершённые задачи могли уже отдать результат в `join_all`, повторно JoinHandle не опрашиваем.
+                if !task.is_finished() {
+                    Self::log_join_result(task.await);
+                }
             }
         }
     }
+
+    fn log_join_result(result: Result<(), JoinError>) {
+        match result {
+            Ok(()) => {}
+            Err(e) if e.is_cancelled() => error!("Task was cancelled"),
+            Err(e) if e.is_panic() => error!("Task panicked: {e:?}"),
+            Err(e) => error!("Join error: {e:?}"),
+        }
+    }
+
+    fn spawn_status_reporter(registry: TaskRegistry, period: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            // Первый tick срабатывает сразу, а сразу после старта статусы неинтересны.
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+                Self::log_task_statuses(&registry);
+            }
+        })
+    }
+
+    fn log_task_statuses(registry: &TaskRegistry) {
+        for (name, status) in registry.snapshot() {
+            info!("Task {}: {:?}", name, status);
+        }
+
+        for (name, value) in registry.gauges() {
+            info!("Gauge {}: {}", name, value);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,175 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use crate::config::config::BackfillConfig;
+use crate::domain::block::Block;
+use crate::infrastructure::collector::chain_source::ChainSource;
+use crate::infrastructure::queue::publishing::PublishLane;
+use crate::infrastructure::state::json_store::JsonStateStore;
+use crate::scheduler::block_watcher::BlockWatcher;
+
+/// Прогресс backfill, сохраняется после каждого блока, чтобы после падения продолжить с того же места.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillProgress {
+    pub from_height: u64,
+    pub to_height: u64,
+    /// Следующая высота для обработки, `None` — диапазон пройден.
+    pub next_height: Option<u64>,
+    pub processed_blocks: u64,
+    pub skipped_heights: Vec<u64>,
+}
+
+pub struct BackfillJob {
+    chain_source: Arc<dyn ChainSource>,
+    backfill_config: BackfillConfig,
+    block_watcher: BlockWatcher,
+    state_store: JsonStateStore,
+}
+
+impl BackfillJob {
+    pub fn new(chain_source: Arc<dyn ChainSource>, backfill_config: BackfillConfig, block_watcher: BlockWatcher, state_store: JsonStateStore, analytics_stream: &str) -> Self {
+        // У каждого диапазона свой producer: его publishing id растут вниз по высотам независимо от live-watcher'а.
+        let publish_lane = PublishLane::Backfill {
+            producer_name: format!("{}-{}", analytics_stream, Self::range_name(&backfill_config)),
+        };
+
+        Self {
+            chain_source,
+            backfill_config,
+            block_watcher: block_watcher.with_publish_lane(publish_lane),
+            state_store,
+        }
+    }
+
+    fn range_name(backfill_config: &BackfillConfig) -> String {
+        let from = backfill_config.get_from_height().unwrap_or(0);
+        let to = backfill_config.get_to_height()
+            .map_or("tip".to_string(), |height| height.to_string());
+
+        format!("backfill_{from}_{to}")
+    }
+
+    /// Ключ состояния зависит от диапазона: смена диапазона в конфиге начинает новый backfill.
+    fn state_name(&self) -> String {
+        Self::range_name(&self.backfill_config)
+    }
+
+    async fn load_or_create_progress(&self) -> Result<BackfillProgress> {
+        if let Some(progress) = self.state_store.load::<BackfillProgress>(&self.state_name())? {
+            info!("Resuming backfill {}..{} from height {:?}", progress.from_height, progress.to_height, progress.next_height);
+            return Ok(progress);
+        }
+
+        let to_height = match self.backfill_config.get_to_height() {
+            Some(height) => height,
+            None => {
+                let latest_blocks = self.chain_source.fetch_latest_blocks().await?;
+                latest_blocks.iter()
+                    .map(|block| block.get_height() as u64)
+                    .max()
+                    .ok_or_else(|| anyhow!("Couldn't resolve chain tip for backfill"))?
+            }
+        };
+        let from_height = self.backfill_config.get_from_height().unwrap_or(0);
+
+        if from_height > to_height {
+            return Err(anyhow!("Bad backfill range: from_height {} > to_height {}", from_height, to_height));
+        }
+
+        info!("Starting backfill {}..{}", from_height, to_height);
+
+        Ok(BackfillProgress {
+            from_height,
+            to_height,
+            next_height: Some(to_height),
+            processed_blocks: 0,
+            skipped_heights: Vec::new(),
+        })
+    }
+
+    /// По `shutdown` останавливается между блоками: прогресс уже сохранён, следующий запуск продолжит с него.
+    pub async fn run(&mut self, shutdown: CancellationToken) -> Result<()> {
+        let state_name = self.state_name();
+        let mut progress = self.load_or_create_progress().await?;
+        let page_delay = Duration::from_millis(self.backfill_config.get_page_delay_ms());
+
+        while let Some(next_height) = progress.next_height {
+            if shutdown.is_cancelled() {
+                info!("Backfill stopped, next height {}", next_height);
+                return Ok(());
+            }
+
+            let blocks = match self.chain_source.fetch_blocks_from_height(next_height).await {
+                Ok(blocks) if !blocks.is_empty() => blocks,
+                Ok(_) => return Err(anyhow!("Empty blocks page for height {}", next_height)),
+                Err(e) => {
+                    error!("Backfill: couldn't fetch blocks from height {}: {}", next_height, e);
+                    tokio::time::sleep(page_delay).await;
+                    continue;
+                }
+            };
+
+            // Страница идёт от next_height вниз; всё, что выше, уже обработано.
+            let mut advanced = false;
+            for block in blocks.iter().filter(|block| (block.get_height() as u64) <= next_height) {
+                let height = block.get_height() as u64;
+                if height < progress.from_height || shutdown.is_cancelled() {
+                    break;
+                }
+
+                let processed = self.process_block_with_retries(block, &shutdown).await;
+                // Запись прервана остановкой: прогресс не сохраняем, блок обработается при следующем запуске.
+                if !processed && shutdown.is_cancelled() {
+                    info!("Backfill stopped, next height {}", height);
+                    return Ok(());
+                }
+
+                if !processed {
+                    warn!("Backfill: block {} skipped after {} attempts", height, self.backfill_config.get_max_block_attempts());
+                    progress.skipped_heights.push(height);
+                } else {
+                    progress.processed_blocks += 1;
+                }
+
+                progress.next_height = height.checked_sub(1)
+                    .filter(|height| *height >= progress.from_height);
+                self.state_store.save(&state_name, &progress)?;
+                advanced = true;
+            }
+
+            if !advanced {
+                return Err(anyhow!("Blocks page for height {} doesn't contain this height", next_height));
+            }
+
+            info!(
+                "Backfill progress: {} processed, {} skipped, next height {:?}",
+                progress.processed_blocks, progress.skipped_heights.len(), progress.next_height
+            );
+            tokio::time::sleep(page_delay).await;
+        }
+
+        info!("Backfill {}..{} finished: {} processed, skipped heights {:?}", progress.from_height, progress.to_height, progress.processed_blocks, progress.skipped_heights);
+
+        Ok(())
+    }
+
+    async fn process_block_with_retries(&self, block: &Block, shutdown: &CancellationToken) -> bool {
+        let max_attempts = self.backfill_config.get_max_block_attempts().max(1);
+
+        for attempt in 1..=max_attempts {
+            match self.block_watcher.process_block_info(block, shutdown).await {
+                Ok(_) => return true,
+                Err(_) if shutdown.is_cancelled() => return false,
+                Err(e) => {
+                    error!("Backfill: processing block {} failed (attempt {}/{}): {:?}", block.get_height(), attempt, max_attempts, e);
+                    tokio::time::sleep(Duration::from_millis(self.backfill_config.get_page_delay_ms())).await;
+                }
+            }
+        }
+
+        false
+    }
+}
\ No newline at end of file
@@ -1,62 +1,366 @@
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use bitcoin::ScriptBuf;
+use chrono::Utc;
 use log::{error, info, warn};
-use reqwest::Client;
-use crate::config::config::Config;
-use crate::domain::block::Block;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
+use crate::application::notifications::NotificationRules;
+use crate::config::config::{Config, PipelineTopology};
+use crate::domain::block::{Block, BlockRef};
+use crate::domain::fee_stats::BlockFeeStats;
+use crate::domain::notification::Notification;
+use crate::domain::reward_anomaly::RewardAnomaly;
 use crate::domain::transaction::Transaction;
-use crate::infrastructure::collector::mempool::{fetch_get_coinbase, fetch_get_coinbase_tx_id, fetch_latest_blocks};
-use crate::infrastructure::queue::queue_service::QueueService;
+use crate::infrastructure::collector::chain_source::ChainSource;
+use crate::infrastructure::db::postgres::{write_until_saved, DbWrite, WriteOutcome};
+use crate::infrastructure::queue::publishing::PublishLane;
+use crate::infrastructure::queue::queue_service::{AnalyticsEvent, BlockAnalyticsMessage, QueueService, ReorgEvent};
+use crate::infrastructure::state::json_store::JsonStateStore;
+use crate::utils::coinbase_commitment::CoinbaseCommitment;
+use crate::utils::pool_identifier::{PoolIdentifier, PoolMatch};
 use crate::utils::script_sig::ParsedScriptSig;
 
+const WATCHER_STATE: &str = "watcher_state";
+/// `guessed_miner` блока, в scriptSig которого не нашлось тега майнера.
+const UNKNOWN_MINER: &str = "unknown";
+
+/// Окно последних обработанных блоков: по нему проверяем связность цепочки и ищем точку форка при reorg.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatcherState {
+    pub recent_blocks: VecDeque<BlockRef>,
+}
+
+impl WatcherState {
+    pub fn tip(&self) -> Option<&BlockRef> {
+        self.recent_blocks.back()
+    }
+
+    pub fn find(&self, hash: &str) -> Option<&BlockRef> {
+        self.recent_blocks.iter().find(|block| block.hash == hash)
+    }
+
+    pub fn push(&mut self, block: BlockRef, window: usize) {
+        self.recent_blocks.push_back(block);
+
+        while self.recent_blocks.len() > window.max(1) {
+            self.recent_blocks.pop_front();
+        }
+    }
+
+    /// Убирает из окна всё выше `height` и возвращает убранные блоки.
+    pub fn split_off_above(&mut self, height: u64) -> Vec<BlockRef> {
+        let position = self.recent_blocks.iter()
+            .position(|block| block.height > height)
+            .unwrap_or(self.recent_blocks.len());
+
+        self.recent_blocks.split_off(position).into()
+    }
+}
+
+/// Блок с разобранной coinbase, ещё не отправленный в очередь.
+pub(crate) struct AnalysedBlock {
+    block: Block,
+    coinbase: Transaction,
+    guessed_miner: String,
+    pool: PoolMatch,
+    fee_stats: Option<BlockFeeStats>,
+    reward_anomaly: Option<RewardAnomaly>,
+}
+
+impl AnalysedBlock {
+    fn to_block_ref(&self) -> BlockRef {
+        BlockRef {
+            height: self.block.get_height() as u64,
+            hash: self.block.get_id(),
+            guessed_miner: Some(self.guessed_miner.clone()),
+            pool_slug: Some(self.pool.slug.clone()),
+        }
+    }
+}
+
 pub struct BlockWatcher {
-    client: Arc<Client>,
+    chain_source: Arc<dyn ChainSource>,
     config: Arc<Config>,
-    rabbitmq_queue_service: Option<Arc<QueueService>>
+    rabbitmq_queue_service: Option<Arc<QueueService>>,
+    state_store: Option<JsonStateStore>,
+    state: WatcherState,
+    pool_identifier: Arc<PoolIdentifier>,
+    /// Только у live-watcher'а: для backfill окна и интервалы между блоками не имеют смысла.
+    notification_rules: Option<Mutex<NotificationRules>>,
+    publish_lane: PublishLane,
+    pipeline: PipelineTopology,
+    /// Канал writer'а БД, если аналитика пишется в БД напрямую.
+    database_sender: Option<Sender<DbWrite>>,
 }
 
 impl BlockWatcher {
-    pub fn new(client: Arc<Client>, config: Arc<Config>, queue_service: Option<Arc<QueueService>>) -> Self {
+    pub fn new(
+        chain_source: Arc<dyn ChainSource>,
+        config: Arc<Config>,
+        queue_service: Option<Arc<QueueService>>,
+        state_store: Option<JsonStateStore>,
+        pool_identifier: Arc<PoolIdentifier>,
+        notification_rules: Option<NotificationRules>,
+    ) -> Self {
         Self {
-            client,
+            chain_source,
             config,
-            rabbitmq_queue_service: queue_service
+            rabbitmq_queue_service: queue_service,
+            state_store,
+            state: WatcherState::default(),
+            pool_identifier,
+            notification_rules: notification_rules.map(Mutex::new),
+            publish_lane: PublishLane::default(),
+            pipeline: PipelineTopology::default(),
+            database_sender: None,
         }
     }
 
-    pub async fn start_monitoring_new_blocks(&mut self) -> anyhow::Result<()> {
+    /// Backfill публикует через свой producer с убывающей по высоте нумерацией.
+    pub fn with_publish_lane(mut self, publish_lane: PublishLane) -> Self {
+        self.publish_lane = publish_lane;
+        self
+    }
+
+    /// Уведомления уходят в RabbitMQ при любой топологии, от неё зависит только аналитика.
+    pub fn with_pipeline(mut self, pipeline: PipelineTopology, database_sender: Option<Sender<DbWrite>>) -> Self {
+        self.pipeline = pipeline;
+        self.database_sender = database_sender.filter(|_| pipeline.writes_to_database());
+        self
+    }
+
+    /// Останавливается по `shutdown` между тиками или между блоками; состояние к этому моменту уже сохранено.
+    pub async fn start_monitoring_new_blocks(&mut self, shutdown: CancellationToken) -> anyhow::Result<()> {
         let mut interval = tokio::time::interval(Duration::from_secs(self.config.get_interval_analytic_blocks()));
 
+        info!("Block watcher started with chain source: {}", self.chain_source.name());
+
+        self.state = self.load_state();
+        match self.state.tip() {
+            Some(tip) => info!("Block watcher resumes after block {} ({})", tip.height, tip.hash),
+            None => info!("Block watcher has no stored tip, starting from the latest blocks"),
+        }
+
         loop {
-            let client_for_blocks = Arc::clone(&self.client);
-            let blocks = fetch_latest_blocks(client_for_blocks, self.config.get_api_url().to_string()).await?;
+            let blocks = self.fetch_unprocessed_blocks().await?;
+
+            self.process_blocks(blocks, &shutdown).await;
+
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.cancelled() => {
+                    info!("Block watcher stopped");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn load_state(&self) -> WatcherState {
+        let Some(state_store) = self.state_store.as_ref() else {
+            return WatcherState::default();
+        };
+
+        state_store.load::<WatcherState>(WATCHER_STATE)
+            .inspect_err(|e| error!("Couldn't load watcher state: {}", e))
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
 
-            self.process_blocks(blocks).await;
-            interval.tick().await;
+    fn save_state(&self) {
+        if let Some(state_store) = &self.state_store
+            && let Err(e) = state_store.save(WATCHER_STATE, &self.state) {
+            error!("Couldn't save watcher state: {}", e);
         }
     }
 
-    async fn process_blocks(&self, blocks: Vec<Block>) {
+    fn remember_block(&mut self, block: BlockRef) {
+        // +1: сама точка форка тоже должна остаться в окне.
+        let window = self.config.get_max_reorg_depth() as usize + 1;
+        self.state.push(block, window);
+    }
+
+    /// Блоки выше последнего обработанного, по возрастанию высоты.
+    /// Если между тиками пришло больше блоков, чем помещается в страницу, догружаем недостающие страницы.
+    async fn fetch_unprocessed_blocks(&self) -> anyhow::Result<Vec<Block>> {
+        let mut blocks = self.chain_source.fetch_latest_blocks().await?;
+
+        if let Some(tip) = self.state.tip() {
+            blocks.retain(|block| block.get_height() as u64 > tip.height);
+
+            while let Some(lowest_height) = blocks.iter().map(|block| block.get_height() as u64).min() {
+                if lowest_height <= tip.height + 1 {
+                    break;
+                }
+
+                info!("Filling gap between processed block {} and block {}", tip.height, lowest_height);
+                let older_blocks: Vec<Block> = self.chain_source.fetch_blocks_from_height(lowest_height - 1)
+                    .await?
+                    .into_iter()
+                    .filter(|block| {
+                        let height = block.get_height() as u64;
+                        height > tip.height && height < lowest_height
+                    })
+                    .collect();
+
+                if older_blocks.is_empty() {
+                    break;
+                }
+
+                blocks.extend(older_blocks);
+            }
+        }
+
+        blocks.sort_by_key(|block| block.get_height());
+
+        Ok(blocks)
+    }
+
+    /// Обрабатываем строго по порядку: при ошибке останавливаемся, чтобы на следующем тике не было дыры.
+    async fn process_blocks(&mut self, blocks: Vec<Block>, shutdown: &CancellationToken) {
         for block in blocks.iter() {
-            if let Err(e) = self.process_block_info(block).await {
+            if shutdown.is_cancelled() {
+                break;
+            }
+
+            // Reorg меняет окно до публикации, поэтому при ошибке откатываем его целиком:
+            // на следующем тике блок (или весь reorg) обработается заново.
+            let state_before = self.state.clone();
+            let result = if self.links_to_tip(block) {
+                self.process_block_info(block, shutdown)
+                    .await
+                    .map(|processed| self.remember_block(processed))
+            } else {
+                self.handle_reorg(block, shutdown).await
+            };
+
+            if let Err(e) = result {
                 error!("Processing block error: {:?}", e);
+                self.state = state_before;
+                break;
             }
+
+            self.save_state();
         }
     }
 
-    async fn process_block_info(&self, block: &Block) -> anyhow::Result<()> {
-        let block_hash = block.get_id();
+    /// Блок следующей высоты должен ссылаться на последний обработанный.
+    fn links_to_tip(&self, block: &Block) -> bool {
+        match self.state.tip() {
+            Some(tip) => block.get_height() as u64 != tip.height + 1 || block.get_previous_block_hash() == tip.hash,
+            None => true,
+        }
+    }
 
-        let client = Arc::clone(&self.client);
-        let url = self.config.get_api_url();
+    /// Идём от нового блока назад по `previousblockhash`, пока не встретим блок из окна — это точка форка.
+    /// Всё, что в окне выше неё, вытеснено новой веткой.
+    async fn handle_reorg(&mut self, block: &Block, shutdown: &CancellationToken) -> anyhow::Result<()> {
+        if let Some(tip) = self.state.tip() {
+            warn!(
+                "Reorg detected: block {} ({}) doesn't link to processed tip {} ({})",
+                block.get_height(), block.get_id(), tip.height, tip.hash
+            );
+        }
 
-        let coinbase_txid = fetch_get_coinbase_tx_id(client, url.to_string(), block_hash.to_string())
-            .await
-            .map_err(|e| anyhow::anyhow!("Get coinbase tx id error: {}", e))?;
+        let max_reorg_depth = self.config.get_max_reorg_depth();
+        let mut new_branch = vec![block.clone()];
+        let mut previous_hash = block.get_previous_block_hash().to_string();
+
+        let fork = loop {
+            if let Some(fork) = self.state.find(&previous_hash) {
+                break Some(fork.clone());
+            }
+
+            if new_branch.len() as u64 > max_reorg_depth {
+                break None;
+            }
+
+            let parent = self.chain_source.fetch_block_by_hash(&previous_hash).await?;
+            previous_hash = parent.get_previous_block_hash().to_string();
+            new_branch.push(parent);
+        };
+
+        let Some(fork) = fork else {
+            error!("Reorg is deeper than {} blocks, fork point is unknown; resetting watcher state", max_reorg_depth);
+            let previous_tip_height = self.state.tip().map_or(0, |tip| tip.height);
+            self.state = WatcherState::default();
+
+            let analysed = self.analyse_block(block).await?;
+            let lane = self.lane_for_height(block.get_height() as u64, previous_tip_height, &block.get_id());
+            self.publish_block_analytics(&analysed, &lane, shutdown).await?;
+            self.remember_block(analysed.to_block_ref());
+            return Ok(());
+        };
+
+        new_branch.reverse();
+        let mut analysed_branch = Vec::with_capacity(new_branch.len());
+        for branch_block in new_branch.iter() {
+            analysed_branch.push(self.analyse_block(branch_block).await?);
+        }
+
+        let orphaned_blocks = self.state.split_off_above(fork.height);
+        let previous_tip_height = orphaned_blocks.last().map_or(fork.height, |orphaned| orphaned.height);
+        let reorg_lane = self.publish_lane.for_reorg(&self.live_producer_name(), &block.get_id());
+        let reorg_event = ReorgEvent {
+            fork_height: fork.height,
+            fork_hash: fork.hash.clone(),
+            orphaned_blocks,
+            new_blocks: analysed_branch.iter().map(AnalysedBlock::to_block_ref).collect(),
+            detected_at: Utc::now().timestamp() as u64,
+        };
 
-        let client_for_coinbase = Arc::clone(&self.client);
-        let coinbase = fetch_get_coinbase(client_for_coinbase, url.to_string(), coinbase_txid)
+        warn!(
+            "Reorg at fork height {}: orphaned {:?}, new branch {:?}",
+            reorg_event.fork_height, reorg_event.orphaned_blocks, reorg_event.new_blocks
+        );
+
+        // Сначала reorg, потом блоки новой ветки: БД должна пометить старые блоки до вставки новых на тех же высотах.
+        self.publish_reorg_event(reorg_event, &reorg_lane, shutdown).await?;
+
+        for analysed in analysed_branch.iter() {
+            // Выше прежней вершины основной producer ещё не публиковал, туда блоки идут как обычно.
+            let lane = if analysed.block.get_height() as u64 <= previous_tip_height {
+                &reorg_lane
+            } else {
+                &self.publish_lane
+            };
+            self.publish_block_analytics(analysed, lane, shutdown).await?;
+            self.remember_block(analysed.to_block_ref());
+        }
+
+        Ok(())
+    }
+
+    /// Ошибка записи в БД или постановки в очередь возвращается: вызывающий не должен сдвигать прогресс.
+    pub(crate) async fn process_block_info(&self, block: &Block, shutdown: &CancellationToken) -> anyhow::Result<BlockRef> {
+        let analysed = self.analyse_block(block).await?;
+        self.publish_block_analytics(&analysed, &self.publish_lane, shutdown).await?;
+
+        Ok(analysed.to_block_ref())
+    }
+
+    /// Основной producer стрима аналитики: от его имени строятся имена producer'ов reorg'ов.
+    fn live_producer_name(&self) -> String {
+        self.config.get_rabbitmq_config().get_streams().get_producer_name()
+    }
+
+    /// Блок на высоте, которую основной producer уже занял, идёт через producer reorg'а, иначе брокер его отбросит.
+    fn lane_for_height(&self, height: u64, published_height: u64, new_tip_hash: &str) -> PublishLane {
+        if height <= published_height {
+            self.publish_lane.for_reorg(&self.live_producer_name(), new_tip_hash)
+        } else {
+            self.publish_lane.clone()
+        }
+    }
+
+    async fn analyse_block(&self, block: &Block) -> anyhow::Result<AnalysedBlock> {
+        let block_hash = block.get_id();
+
+        let coinbase = self.chain_source.fetch_coinbase(&block_hash)
             .await
             .map_err(|e| anyhow::anyhow!("Get coinbase error: {}", e))?;
 
@@ -73,28 +377,76 @@ impl BlockWatcher {
         info!("----   Size: {}   ----", size);
         info!("----   merkle_root: {}   ----", merkle_root);
         info!("----   difficulty: {}   ----", difficulty);
-        self.report_coinbase_details(block, &coinbase).await;
+        let guessed_miner = self.report_coinbase_details(&coinbase)?;
+        let pool = self.identify_pool(&coinbase);
+        info!("----   Pool: {} ({}, matched by {})   ----", pool.name, pool.slug, pool.method.as_str());
+        let fee_stats = self.calculate_fee_stats(&block_hash).await;
+        let reward_anomaly = RewardAnomaly::detect(
+            height as u64,
+            &block_hash,
+            coinbase.get_full_reward_value(),
+            fee_stats.as_ref().map(|stats| stats.total_fees),
+        );
+        if let Some(anomaly) = &reward_anomaly {
+            warn!("----   Reward anomaly: {} by {} sat   ----", anomaly.kind.as_str(), anomaly.difference.abs());
+        }
         info!("------------  Block information closed  ------------");
 
-        Ok(())
+        Ok(AnalysedBlock {
+            block: block.clone(),
+            coinbase,
+            guessed_miner,
+            pool,
+            fee_stats,
+            reward_anomaly,
+        })
     }
 
-    async fn report_coinbase_details(&self, block: &Block, coinbase: &Transaction) {
-        let script_sig = coinbase.get_vin_scriptsig();
+    /// Ошибка здесь не должна ронять обработку блока: без статистики блок всё равно публикуется.
+    async fn calculate_fee_stats(&self, block_hash: &str) -> Option<BlockFeeStats> {
+        if !self.config.is_full_block_analysis_enabled() {
+            return None;
+        }
 
-        let bytes = hex::decode(script_sig).expect("Invalid hex");
-        let script = ScriptBuf::from_bytes(bytes);
+        let fee_stats = self.chain_source.fetch_block_transactions(block_hash)
+            .await
+            .and_then(|transactions| BlockFeeStats::from_transactions(&transactions));
+
+        match fee_stats {
+            Ok(fee_stats) => {
+                info!("----   Fees: {} sat, vsize: {}, median feerate: {:.2} sat/vB   ----",
+                    fee_stats.total_fees, fee_stats.total_vsize, fee_stats.median_feerate);
+                Some(fee_stats)
+            }
+            Err(e) => {
+                error!("Couldn't calculate fee stats for block {}: {}", block_hash, e);
+                None
+            }
+        }
+    }
 
-        let parsed_script_opt = ParsedScriptSig::from(&script);
+    fn identify_pool(&self, coinbase: &Transaction) -> PoolMatch {
+        let coinbase_script = hex::decode(coinbase.get_vin_scriptsig()).unwrap_or_default();
+        let reward_address = coinbase.get_main_reward_address()
+            .and_then(|address| address.as_deref());
 
+        self.pool_identifier.identify(&coinbase_script, reward_address)
+    }
 
-        let parsed_script = match parsed_script_opt {
+    /// Ошибка — только битый hex scriptSig. Если тег майнера не разобрался (ранние coinbase вроде `04ffff001d0104`),
+    /// блок всё равно сохраняется и публикуется, а майнер считается неизвестным.
+    fn report_coinbase_details(&self, coinbase: &Transaction) -> anyhow::Result<String> {
+        let script_sig = coinbase.get_vin_scriptsig();
+
+        let bytes = hex::decode(script_sig)
+            .map_err(|e| anyhow::anyhow!("Invalid coinbase scriptSig hex {}: {}", script_sig, e))?;
+        let script = ScriptBuf::from_bytes(bytes);
+
+        let guessed_miner = match ParsedScriptSig::from(&script) {
+            Some(parsed_script) => parsed_script.guessed_miner,
             None => {
-                error!("Error parsed scriptSig.");
-                return
-            },
-            Some(parsed_script) => {
-                parsed_script
+                warn!("Couldn't parse coinbase scriptSig {}, miner is unknown", script_sig);
+                UNKNOWN_MINER.to_string()
             }
         };
 
@@ -102,7 +454,6 @@ impl BlockWatcher {
         let address_miner = coinbase.get_main_reward_address();
         let full_reward = coinbase.get_full_reward_value();
         let rewards_and_addresses = coinbase.get_rewards_value_and_address();
-        let guessed_miner = parsed_script.guessed_miner;
 
         info!("------  Coinbase information  ------");
         info!("--  Main reward: {:?}  --", main_reward);
@@ -110,17 +461,117 @@ impl BlockWatcher {
         info!("--  Full reward: {}  --", full_reward);
         info!("--  Rewards and addresses: {:?}  --", rewards_and_addresses);
         info!("--  Guessed miner: {}  --", guessed_miner);
+        for commitment in CoinbaseCommitment::decode_outputs(coinbase.get_vouts()) {
+            info!("--  Commitment #{}: {} {}  --", commitment.vout_index, commitment.kind.as_str(), commitment.payload_hex);
+        }
         info!("------  Closed Coinbase Information  ------");
 
+        Ok(guessed_miner)
+    }
 
-        if let Some(queue_service) = &self.rabbitmq_queue_service {
-            if let Err(e) = queue_service.send_block_analytics(block, coinbase, guessed_miner).await {
-                error!("Error sending block analytics: {:?}", e);
-            } else {
-                info!("Block analytics sent successfully for block {}", block.get_height());
+    /// Уведомления считаются только после записи и постановки в очередь: при повторе блока они не задвоятся.
+    async fn publish_block_analytics(&self, analysed: &AnalysedBlock, lane: &PublishLane, shutdown: &CancellationToken) -> anyhow::Result<()> {
+        let guessed_miner = analysed.guessed_miner.clone();
+        let block = &analysed.block;
+
+        let analytics_message = BlockAnalyticsMessage::new(
+            block,
+            &analysed.coinbase,
+            guessed_miner.clone(),
+            &analysed.pool,
+            analysed.fee_stats.as_ref(),
+            analysed.reward_anomaly.as_ref(),
+        );
+
+        let subject = format!("block {}", block.get_height());
+        self.write_to_database(AnalyticsEvent::Block(analytics_message.clone()), &subject, shutdown).await?;
+
+        let Some(queue_service) = &self.rabbitmq_queue_service else {
+            if self.database_sender.is_none() {
+                warn!("Neither queue service nor database is available, skipping analytics sending");
+            }
+            return Ok(());
+        };
+
+        if self.pipeline.publishes_to_queue() {
+            let target = lane.block_target(block.get_height() as u64);
+            queue_service.send_block_analytics(analytics_message, target)
+                .await
+                .map_err(|e| anyhow::anyhow!("Error sending block analytics for {}: {}", subject, e))?;
+            info!("Block analytics sent successfully for block {}", block.get_height());
+        }
+
+        let notifications = self.with_notification_rules(|rules| rules.on_block(
+            block,
+            &analysed.coinbase,
+            &guessed_miner,
+            &analysed.pool,
+            analysed.reward_anomaly.as_ref(),
+        ));
+        self.send_notifications(queue_service, notifications).await;
+
+        Ok(())
+    }
+
+    async fn publish_reorg_event(&self, reorg_event: ReorgEvent, lane: &PublishLane, shutdown: &CancellationToken) -> anyhow::Result<()> {
+        let subject = format!("reorg at fork height {}", reorg_event.fork_height);
+        self.write_to_database(AnalyticsEvent::Reorg(reorg_event.clone()), &subject, shutdown).await?;
+
+        let Some(queue_service) = &self.rabbitmq_queue_service else {
+            if self.database_sender.is_none() {
+                warn!("Neither queue service nor database is available, skipping reorg event sending");
+            }
+            return Ok(());
+        };
+
+        if self.pipeline.publishes_to_queue() {
+            let target = lane.reorg_target(reorg_event.fork_height);
+            queue_service.send_reorg_event(reorg_event.clone(), target)
+                .await
+                .map_err(|e| anyhow::anyhow!("Error sending {}: {}", subject, e))?;
+        }
+
+        let notifications = self.with_notification_rules(|rules| rules.on_reorg(&reorg_event));
+        self.send_notifications(queue_service, notifications).await;
+
+        Ok(())
+    }
+
+    /// Запись подтверждается writer'ом. Пока БД недоступна, повторяем без ограничения;
+    /// отказ самой БД, остановка writer'а или сервиса — ошибка, и watcher не сдвигает вершину.
+    async fn write_to_database(&self, analytics_event: AnalyticsEvent, subject: &str, shutdown: &CancellationToken) -> anyhow::Result<()> {
+        let Some(database_sender) = &self.database_sender else {
+            return Ok(());
+        };
+
+        let max_attempts = self.config.get_rabbitmq_config().get_max_db_write_attempts().max(1);
+        match write_until_saved(database_sender, analytics_event, subject, max_attempts, shutdown).await? {
+            WriteOutcome::Saved => Ok(()),
+            WriteOutcome::Rejected(reason) => Err(anyhow::anyhow!("Database rejected {}: {}", subject, reason)),
+            WriteOutcome::Interrupted => Err(anyhow::anyhow!("Writing {} was interrupted by shutdown", subject)),
+        }
+    }
+
+    fn with_notification_rules(&self, evaluate: impl FnOnce(&mut NotificationRules) -> Vec<Notification>) -> Vec<Notification> {
+        let Some(notification_rules) = &self.notification_rules else {
+            return Vec::new();
+        };
+
+        match notification_rules.lock() {
+            Ok(mut rules) => evaluate(&mut rules),
+            Err(e) => {
+                error!("Notification rules are poisoned: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    async fn send_notifications(&self, queue_service: &QueueService, notifications: Vec<Notification>) {
+        for notification in notifications.iter() {
+            match queue_service.send_notification(notification).await {
+                Ok(_) => info!("Notification sent: {:?}", notification),
+                Err(e) => error!("Error sending notification: {:?}", e),
             }
-        } else {
-            warn!("Queue service not available, skipping analytics sending");
         }
     }
 }
\ No newline at end of file
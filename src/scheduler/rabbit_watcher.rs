@@ -1,50 +1,82 @@
 use std::sync::Arc;
 use anyhow::Result;
-use rabbitmq_stream_client::types::OffsetSpecification;
 use sqlx::PgPool;
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
 use crate::config::config::Config;
-use crate::infrastructure::db::postgres::Database;
-use crate::infrastructure::queue::queue_service::{BlockAnalyticsMessage, QueueService};
+use crate::infrastructure::db::consumer_lease::ConsumerLease;
+use crate::infrastructure::db::postgres::DbWrite;
+use crate::infrastructure::queue::dead_letter::DeadLetterQueue;
+use crate::infrastructure::queue::message_bus::MessageBus;
+use crate::infrastructure::queue::queue_service::QueueService;
 
 pub struct MessageIngestionService {
-    rabbit_queue_service: Option<Arc<QueueService>>,
-    #[allow(dead_code)]
+    message_bus: Option<Arc<dyn MessageBus>>,
     config: Arc<Config>,
+    /// Если задан, партицию читает только экземпляр, взявший на неё advisory lock в этой БД.
+    lease_pool: Option<Arc<PgPool>>,
 }
 
 impl MessageIngestionService {
-    pub fn new(config: Arc<Config>, queue_service: Option<Arc<QueueService>>) -> Self {
+    pub fn new(config: Arc<Config>, message_bus: Option<Arc<dyn MessageBus>>) -> Self {
         Self {
-            rabbit_queue_service: queue_service,
+            message_bus,
             config,
+            lease_pool: None,
         }
     }
 
-    pub async fn start_monitoring_rabbit_messages(&mut self, db_sender: Sender<BlockAnalyticsMessage>, db_receiver: Receiver<BlockAnalyticsMessage>, db_pool: Arc<PgPool>) -> Result<()> {
-        if self.rabbit_queue_service.is_none() {
-            return Err(anyhow::anyhow!("Error RabbitMQ: Couldn't to connect with RabbitMQ"));
-        }
+    pub fn with_single_active_consumer(mut self, lease_pool: Arc<PgPool>) -> Self {
+        self.lease_pool = Some(lease_pool);
+        self
+    }
 
-        // Возможно чуть позже подумаю, как быть, если вдруг rabbitmq не работает, вдруг решу записывать на прямую в db.
-        if let Some(queue_service) = &self.rabbit_queue_service {
-            let rabbitmq_client = Arc::clone(&queue_service.rabbitmq_client);
+    /// Читает одну партицию стрима аналитики (или весь стрим, если он не разбит).
+    /// Каждый запуск создаёт новую подписку, поэтому после ошибки чтение продолжается с сохранённого offset'а.
+    /// С single active consumer сначала ждёт lease на партицию; если lease потерян, чтение останавливается с ошибкой.
+    pub async fn start_monitoring_rabbit_messages(&self, stream: &str, db_sender: Sender<DbWrite>, shutdown: CancellationToken) -> Result<()> {
+        let Some(message_bus) = &self.message_bus else {
+            return Err(anyhow::anyhow!("Error message bus: it isn't available"));
+        };
 
-            let environment = rabbitmq_client.get_environment();
+        let rabbitmq_config = self.config.get_rabbitmq_config();
+        let streams = rabbitmq_config.get_streams();
+        let dead_letters = DeadLetterQueue::new(Arc::clone(message_bus), streams).with_source_stream(stream);
+        let max_db_write_attempts = rabbitmq_config.get_max_db_write_attempts().max(1);
 
-            let consumer_mining_analytics = environment
-                .consumer()
-                .name("reader-for-block-analytics")
-                .offset(OffsetSpecification::Next)
-                .build("mining-analytics")
-                .await?;
+        let lease = match &self.lease_pool {
+            Some(lease_pool) => match ConsumerLease::acquire(lease_pool, streams.get_consumer_name(), stream, &shutdown).await? {
+                Some(lease) => Some(lease),
+                None => return Ok(()),
+            },
+            None => None,
+        };
 
-            let _ = tokio::spawn(async move {
-                Database::queue_messages_reader(db_receiver, db_pool).await;
-            });
-            QueueService::read_messages_from_rabbitmq_mining_analytics(consumer_mining_analytics, db_sender).await;
-        }
+        let subscription = message_bus.subscribe(stream, streams.get_consumer_name()).await?;
+        let reader_shutdown = shutdown.child_token();
+        let reader = QueueService::read_analytics_messages(subscription, db_sender, &dead_letters, max_db_write_attempts, reader_shutdown.clone());
 
-        Ok(())
+        let Some(mut lease) = lease else {
+            return reader.await;
+        };
+
+        tokio::pin!(reader);
+        let lease_lost = tokio::select! {
+            result = &mut reader => Ok(result),
+            err = lease.lost() => Err(err),
+        };
+
+        match lease_lost {
+            Ok(result) => {
+                lease.release().await;
+                result
+            }
+            // Lock мог перейти к другому экземпляру: дописываем текущее сообщение и отдаём партицию.
+            Err(err) => {
+                reader_shutdown.cancel();
+                reader.await?;
+                Err(err)
+            }
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,42 @@
+#[cfg(unix)]
+use log::error;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Порядок остановки: сначала перестаём производить события, затем дописываем и дочитываем RabbitMQ,
+/// последней останавливается запись в БД, чтобы consumer успел сохранить offset'ы.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownStage {
+    Sources,
+    Queue,
+    Storage,
+}
+
+impl ShutdownStage {
+    pub const ORDER: [ShutdownStage; 3] = [ShutdownStage::Sources, ShutdownStage::Queue, ShutdownStage::Storage];
+}
+
+/// Ждёт SIGINT или SIGTERM и возвращает имя сигнала.
+#[cfg(unix)]
+pub async fn wait_for_signal() -> &'static str {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            error!("Couldn't listen for SIGTERM, only SIGINT will stop the service: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return "SIGINT";
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = terminate.recv() => "SIGTERM",
+    }
+}
+
+/// Вне Unix SIGTERM нет: останавливаемся только по Ctrl+C.
+#[cfg(not(unix))]
+pub async fn wait_for_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "SIGINT"
+}
\ No newline at end of file
@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::{error, info, warn};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::config::SupervisorConfig;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskStatus {
+    Running { restarts: u32 },
+    /// Упала и ждёт перезапуска.
+    Restarting { restarts: u32, last_error: String },
+    /// Завершилась штатно.
+    Finished,
+    /// Исчерпала лимит перезапусков.
+    Failed { restarts: u32, last_error: String },
+}
+
+/// Текущее состояние всех задач под супервизором и счётчики, которые задачи публикуют рядом со статусом.
+#[derive(Debug, Clone, Default)]
+pub struct TaskRegistry {
+    statuses: Arc<RwLock<BTreeMap<String, TaskStatus>>>,
+    gauges: Arc<RwLock<BTreeMap<String, Arc<AtomicUsize>>>>,
+}
+
+impl TaskRegistry {
+    fn set(&self, name: &str, status: TaskStatus) {
+        info!("Task {} status: {:?}", name, status);
+
+        match self.statuses.write() {
+            Ok(mut statuses) => {
+                statuses.insert(name.to_string(), status);
+            }
+            Err(e) => error!("Task registry is poisoned: {}", e),
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, TaskStatus> {
+        self.statuses.read()
+            .map(|statuses| statuses.clone())
+            .unwrap_or_default()
+    }
+
+    /// Значение читается при каждом `gauges()`, задача обновляет его сама.
+    pub fn register_gauge(&self, name: &str, gauge: Arc<AtomicUsize>) {
+        match self.gauges.write() {
+            Ok(mut gauges) => {
+                gauges.insert(name.to_string(), gauge);
+            }
+            Err(e) => error!("Task registry is poisoned: {}", e),
+        }
+    }
+
+    pub fn gauges(&self) -> BTreeMap<String, usize> {
+        self.gauges.read()
+            .map(|gauges| gauges.iter()
+                .map(|(name, gauge)| (name.clone(), gauge.load(Ordering::Relaxed)))
+                .collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Запускает долгоживущие компоненты как именованные задачи и перезапускает их при ошибке или панике
+/// с экспоненциальной задержкой. Штатное завершение (`Ok`) не перезапускается.
+#[derive(Clone)]
+pub struct Supervisor {
+    config: SupervisorConfig,
+    registry: TaskRegistry,
+}
+
+impl Supervisor {
+    pub fn new(config: SupervisorConfig) -> Self {
+        Self {
+            config,
+            registry: TaskRegistry::default(),
+        }
+    }
+
+    pub fn registry(&self) -> TaskRegistry {
+        self.registry.clone()
+    }
+
+    /// `run` вызывается на каждый запуск, поэтому всё состояние задачи должно жить снаружи (обычно за `Arc<Mutex<_>>`).
+    /// После отмены `shutdown` задача должна завершиться с `Ok`; упавшую после отмены задачу не перезапускаем.
+    pub fn spawn<F, Fut>(&self, name: &str, shutdown: CancellationToken, run: F) -> JoinHandle<()>
+    where
+        F: Fn(CancellationToken) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let name = name.to_string();
+        let config = self.config.clone();
+        let registry = self.registry.clone();
+
+        tokio::spawn(async move {
+            let initial_delay = Duration::from_millis(config.get_restart_initial_delay_ms());
+            let max_delay = Duration::from_millis(config.get_restart_max_delay_ms());
+
+            let mut restarts = 0;
+            let mut delay = initial_delay;
+
+            loop {
+                registry.set(&name, TaskStatus::Running { restarts });
+                let started_at = Instant::now();
+
+                // Отдельная задача, чтобы паника внутри не уронила супервизор.
+                let last_error = match tokio::spawn(run(shutdown.clone())).await {
+                    Ok(Ok(())) => {
+                        registry.set(&name, TaskStatus::Finished);
+                        return;
+                    }
+                    Ok(Err(e)) => format!("{:#}", e),
+                    Err(e) if e.is_panic() => format!("panic: {:?}", e),
+                    Err(e) => format!("{:?}", e),
+                };
+                error!("Task {} failed: {}", name, last_error);
+
+                if shutdown.is_cancelled() {
+                    registry.set(&name, TaskStatus::Failed { restarts, last_error });
+                    return;
+                }
+
+                if config.get_max_restarts().is_some_and(|max_restarts| restarts >= max_restarts) {
+                    error!("Task {} exceeded {} restarts, giving up", name, restarts);
+                    registry.set(&name, TaskStatus::Failed { restarts, last_error });
+                    return;
+                }
+
+                // Если задача успела проработать дольше максимальной задержки, считаем, что она была здорова.
+                if started_at.elapsed() > max_delay {
+                    delay = initial_delay;
+                }
+
+                restarts += 1;
+                registry.set(&name, TaskStatus::Restarting { restarts, last_error });
+                warn!("Restarting task {} in {:?}", name, delay);
+
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = shutdown.cancelled() => {
+                        registry.set(&name, TaskStatus::Finished);
+                        return;
+                    }
+                }
+                delay = (delay * 2).min(max_delay);
+            }
+        })
+    }
+}
\ No newline at end of file
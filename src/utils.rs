@@ -1,2 +1,6 @@
 pub mod script_sig;
-pub mod block_reward;
\ No newline at end of file
+pub mod block_reward;
+pub mod pool_identifier;
+pub mod coinbase_commitment;
+pub mod merged_mining;
+pub mod murmur3;
\ No newline at end of file
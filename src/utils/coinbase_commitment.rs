@@ -0,0 +1,167 @@
+use bitcoin::blockdata::script::{Instruction, ScriptBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::transaction::VectorOutputs;
+
+/// Что записано в нулевом (обычно OP_RETURN) выходе coinbase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitmentKind {
+    /// SegWit witness commitment (BIP141), `aa21a9ed` + 32 байта.
+    WitnessCommitment,
+    /// RSK merged mining: `RSKBLOCK:` + хэш блока RSK.
+    Rsk,
+    /// Namecoin-style aux-pow `fabe6d6d` (Namecoin, Syscoin, Elastos и др.).
+    AuxPow,
+    Hathor,
+    CoreDao,
+    Stacks,
+    ExSat,
+    Unknown,
+}
+
+impl CommitmentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommitmentKind::WitnessCommitment => "witness_commitment",
+            CommitmentKind::Rsk => "rsk",
+            CommitmentKind::AuxPow => "aux_pow",
+            CommitmentKind::Hathor => "hathor",
+            CommitmentKind::CoreDao => "core_dao",
+            CommitmentKind::Stacks => "stacks",
+            CommitmentKind::ExSat => "exsat",
+            CommitmentKind::Unknown => "unknown",
+        }
+    }
+}
+
+/// Префиксы данных OP_RETURN, по которым узнаём известные коммитменты.
+const KNOWN_PREFIXES: &[(&[u8], CommitmentKind)] = &[
+    (&[0xaa, 0x21, 0xa9, 0xed], CommitmentKind::WitnessCommitment),
+    (b"RSKBLOCK:", CommitmentKind::Rsk),
+    (&[0xfa, 0xbe, 0x6d, 0x6d], CommitmentKind::AuxPow),
+    (b"Hath", CommitmentKind::Hathor),
+    (b"CORE", CommitmentKind::CoreDao),
+    (b"EXSAT", CommitmentKind::ExSat),
+    (b"X2", CommitmentKind::Stacks),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoinbaseCommitment {
+    pub vout_index: u32,
+    pub kind: CommitmentKind,
+    /// Данные без префикса, hex.
+    pub payload_hex: String,
+}
+
+impl CoinbaseCommitment {
+    /// Разбирает все нулевые и OP_RETURN выходы coinbase.
+    pub fn decode_outputs(vouts: &[VectorOutputs]) -> Vec<CoinbaseCommitment> {
+        vouts.iter()
+            .enumerate()
+            .filter_map(|(vout_index, vout)| {
+                let script = ScriptBuf::from_hex(vout.get_scriptpubkey()).ok()?;
+                if vout.get_value() != 0 && !script.is_op_return() {
+                    return None;
+                }
+
+                let (kind, payload) = Self::classify(&script);
+                Some(CoinbaseCommitment {
+                    vout_index: vout_index as u32,
+                    kind,
+                    payload_hex: hex::encode(payload),
+                })
+            })
+            .collect()
+    }
+
+    fn classify(script: &ScriptBuf) -> (CommitmentKind, Vec<u8>) {
+        if !script.is_op_return() {
+            return (CommitmentKind::Unknown, script.to_bytes());
+        }
+
+        let data = Self::op_return_data(script);
+        KNOWN_PREFIXES.iter()
+            .find(|(prefix, _)| data.starts_with(prefix))
+            .map(|(prefix, kind)| (*kind, data[prefix.len()..].to_vec()))
+            .unwrap_or((CommitmentKind::Unknown, data))
+    }
+
+    /// Склеивает все push'и после OP_RETURN (коммитмент может быть разбит на несколько push'ей).
+    fn op_return_data(script: &ScriptBuf) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        for instruction in script.instructions().flatten() {
+            if let Instruction::PushBytes(bytes) = instruction {
+                data.extend_from_slice(bytes.as_bytes());
+            }
+        }
+
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(scriptpubkey: &str, value: i64) -> VectorOutputs {
+        VectorOutputs::new(scriptpubkey.to_string(), String::new(), String::new(), None, value)
+    }
+
+    fn payout() -> VectorOutputs {
+        output("0014751e76e8199196d454941c45d1b3a323f1433bd6", 312_500_000)
+    }
+
+    #[test]
+    fn decodes_witness_commitment() {
+        let commitment_hash = "11".repeat(32);
+        let commitments = CoinbaseCommitment::decode_outputs(&[payout(), output(&format!("6a24aa21a9ed{}", commitment_hash), 0)]);
+
+        assert_eq!(commitments, vec![CoinbaseCommitment {
+            vout_index: 1,
+            kind: CommitmentKind::WitnessCommitment,
+            payload_hex: commitment_hash,
+        }]);
+    }
+
+    #[test]
+    fn decodes_rsk_tag_in_one_or_several_pushes() {
+        let rsk_hash = "22".repeat(32);
+        let single_push = output(&format!("6a29{}{}", hex::encode("RSKBLOCK:"), rsk_hash), 0);
+        // OP_RETURN <"RSKBLOCK:"> <hash>: push'и склеиваются.
+        let multi_push = output(&format!("6a09{}20{}", hex::encode("RSKBLOCK:"), rsk_hash), 0);
+
+        let commitments = CoinbaseCommitment::decode_outputs(&[single_push, multi_push]);
+
+        assert_eq!(commitments.len(), 2);
+        for (vout_index, commitment) in commitments.iter().enumerate() {
+            assert_eq!(commitment.vout_index, vout_index as u32);
+            assert_eq!(commitment.kind, CommitmentKind::Rsk);
+            assert_eq!(commitment.payload_hex, rsk_hash);
+        }
+    }
+
+    #[test]
+    fn keeps_unknown_outputs_whole() {
+        let unknown_op_return = output(&format!("6a05{}", hex::encode("hello")), 0);
+        // Нулевой выход без OP_RETURN тоже считается коммитментом, payload — весь скрипт.
+        let zero_value_p2pkh = output("76a914000000000000000000000000000000000000000088ac", 0);
+
+        let commitments = CoinbaseCommitment::decode_outputs(&[payout(), unknown_op_return, zero_value_p2pkh]);
+
+        assert_eq!(commitments, vec![
+            CoinbaseCommitment { vout_index: 1, kind: CommitmentKind::Unknown, payload_hex: hex::encode("hello") },
+            CoinbaseCommitment {
+                vout_index: 2,
+                kind: CommitmentKind::Unknown,
+                payload_hex: "76a914000000000000000000000000000000000000000088ac".to_string(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn skips_paying_outputs() {
+        assert!(CoinbaseCommitment::decode_outputs(&[payout()]).is_empty());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+use crate::utils::coinbase_commitment::{CoinbaseCommitment, CommitmentKind};
+
+/// Маркер aux-pow (Namecoin, Syscoin, Elastos и др.), который пулы кладут в scriptSig coinbase.
+const AUX_POW_MAGIC: [u8; 4] = [0xfa, 0xbe, 0x6d, 0x6d];
+/// После маркера: корень aux merkle-дерева (32 байта), размер дерева и nonce (по 4 байта, little-endian).
+const AUX_POW_HEADER_LEN: usize = AUX_POW_MAGIC.len() + 32 + 4 + 4;
+
+/// Заголовок aux-pow из scriptSig coinbase.
+/// Какие именно сети за ним стоят, по coinbase биткоина не узнать: chain id есть только в заголовках aux-блоков.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuxPowHeader {
+    pub merkle_root_hex: String,
+    /// Число слотов aux merkle-дерева — верхняя граница числа смержмайненных aux-pow сетей.
+    pub merkle_size: u32,
+    pub merkle_nonce: u32,
+}
+
+impl AuxPowHeader {
+    /// Ищет маркер `fabe6d6d` в scriptSig и разбирает заголовок за ним.
+    pub fn parse(coinbase_script: &[u8]) -> Option<AuxPowHeader> {
+        let start = coinbase_script.windows(AUX_POW_MAGIC.len())
+            .position(|window| window == AUX_POW_MAGIC)?;
+        let header = coinbase_script.get(start..start + AUX_POW_HEADER_LEN)?;
+
+        let merkle_root = &header[4..36];
+        let merkle_size = u32::from_le_bytes(header[36..40].try_into().ok()?);
+        let merkle_nonce = u32::from_le_bytes(header[40..44].try_into().ok()?);
+
+        Some(AuxPowHeader {
+            merkle_root_hex: hex::encode(merkle_root),
+            merkle_size,
+            merkle_nonce,
+        })
+    }
+}
+
+/// Какие маркеры merge mining есть в блоке.
+/// Сети с собственным тегом в OP_RETURN (RSK, Hathor, CoreDAO, Stacks, exSat) различаются,
+/// а все Namecoin-style сети сводятся к одному `aux_pow`: их состав виден только по `AuxPowHeader::merkle_size`.
+pub fn detect_merged_mining(coinbase_script: &[u8], commitments: &[CoinbaseCommitment]) -> Vec<CommitmentKind> {
+    let mut markers = Vec::new();
+
+    let aux_pow_in_script = coinbase_script.windows(AUX_POW_MAGIC.len())
+        .any(|window| window == AUX_POW_MAGIC);
+    if aux_pow_in_script {
+        markers.push(CommitmentKind::AuxPow);
+    }
+
+    for commitment in commitments {
+        let is_merge_mined = !matches!(commitment.kind, CommitmentKind::WitnessCommitment | CommitmentKind::Unknown);
+        if is_merge_mined && !markers.contains(&commitment.kind) {
+            markers.push(commitment.kind);
+        }
+    }
+
+    markers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_aux_pow_header_after_the_height_push() {
+        let mut script = hex::decode("0342340c").unwrap();
+        script.extend_from_slice(&AUX_POW_MAGIC);
+        script.extend_from_slice(&[0x11; 32]);
+        script.extend_from_slice(&4u32.to_le_bytes());
+        script.extend_from_slice(&7u32.to_le_bytes());
+        script.extend_from_slice(b"/pool/");
+
+        let header = AuxPowHeader::parse(&script).unwrap();
+
+        assert_eq!(header.merkle_root_hex, "11".repeat(32));
+        assert_eq!(header.merkle_size, 4);
+        assert_eq!(header.merkle_nonce, 7);
+        assert_eq!(detect_merged_mining(&script, &[]), vec![CommitmentKind::AuxPow]);
+    }
+
+    #[test]
+    fn truncated_aux_pow_header_is_still_flagged() {
+        let mut script = AUX_POW_MAGIC.to_vec();
+        script.extend_from_slice(&[0x11; 8]);
+
+        assert_eq!(AuxPowHeader::parse(&script), None);
+        assert_eq!(detect_merged_mining(&script, &[]), vec![CommitmentKind::AuxPow]);
+    }
+}
\ No newline at end of file
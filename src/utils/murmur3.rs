@@ -0,0 +1,31 @@
+const C1: u32 = 0xcc9e_2d51;
+const C2: u32 = 0x1b87_3593;
+
+/// MurmurHash3 x86_32. Им же маршрутизируют по ключу super stream'а клиенты RabbitMQ.
+pub fn murmur3_32(key: &[u8], seed: u32) -> u32 {
+    let mut hash = seed;
+
+    let chunks = key.chunks_exact(4);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let block = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        hash ^= mix(block);
+        hash = hash.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+
+    if !tail.is_empty() {
+        let block = tail.iter().rev().fold(0u32, |block, byte| (block << 8) | *byte as u32);
+        hash ^= mix(block);
+    }
+
+    hash ^= key.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+fn mix(block: u32) -> u32 {
+    block.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2)
+}
\ No newline at end of file
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolMatchMethod {
+    PayoutAddress,
+    CoinbaseTag,
+    Unknown,
+}
+
+impl PoolMatchMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PoolMatchMethod::PayoutAddress => "payout_address",
+            PoolMatchMethod::CoinbaseTag => "coinbase_tag",
+            PoolMatchMethod::Unknown => "unknown",
+        }
+    }
+}
+
+/// Результат определения пула: каноническое имя, slug и по какому признаку он найден.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolMatch {
+    pub name: String,
+    pub slug: String,
+    pub method: PoolMatchMethod,
+}
+
+impl PoolMatch {
+    pub fn unknown() -> Self {
+        Self {
+            name: "Unknown".to_string(),
+            slug: "unknown".to_string(),
+            method: PoolMatchMethod::Unknown,
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        self.method == PoolMatchMethod::Unknown
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ClassicPoolInfo {
+    name: String,
+}
+
+/// Классический `pools.json`: подстроки coinbase и адреса выплат, каждый указывает на пул.
+#[derive(Debug, Deserialize)]
+struct ClassicPoolsFile {
+    #[serde(default)]
+    coinbase_tags: HashMap<String, ClassicPoolInfo>,
+    #[serde(default)]
+    payout_addresses: HashMap<String, ClassicPoolInfo>,
+}
+
+/// Список пулов (формат pools-v2 у mempool): `tags`/`regexes` — регулярные выражения по scriptSig.
+#[derive(Debug, Deserialize)]
+struct PoolDefinition {
+    name: String,
+    slug: Option<String>,
+    #[serde(default)]
+    addresses: Vec<String>,
+    #[serde(default, alias = "regexes")]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PoolsFile {
+    Classic(ClassicPoolsFile),
+    List(Vec<PoolDefinition>),
+}
+
+#[derive(Debug)]
+struct Pool {
+    name: String,
+    slug: String,
+}
+
+#[derive(Debug, Default)]
+pub struct PoolIdentifier {
+    pools: Vec<Pool>,
+    tags: Vec<(Regex, usize)>,
+    addresses: HashMap<String, usize>,
+}
+
+impl PoolIdentifier {
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let file = File::open(path)
+            .map_err(|e| anyhow!("Couldn't open pools file {}: {}", path, e))?;
+        let pools_file: PoolsFile = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| anyhow!("Couldn't parse pools file {}: {}", path, e))?;
+
+        Self::from_pools_file(pools_file)
+    }
+
+    fn from_pools_file(pools_file: PoolsFile) -> Result<Self> {
+        let definitions = match pools_file {
+            PoolsFile::List(definitions) => definitions,
+            PoolsFile::Classic(classic) => Self::classic_to_definitions(classic),
+        };
+
+        Self::from_definitions(definitions)
+    }
+
+    /// В классическом формате теги — обычные подстроки, поэтому экранируем их в regex.
+    fn classic_to_definitions(classic: ClassicPoolsFile) -> Vec<PoolDefinition> {
+        let mut by_name: HashMap<String, PoolDefinition> = HashMap::new();
+
+        for (tag, info) in classic.coinbase_tags {
+            by_name.entry(info.name.clone())
+                .or_insert_with(|| PoolDefinition { name: info.name, slug: None, addresses: vec![], tags: vec![] })
+                .tags
+                .push(regex::escape(&tag));
+        }
+
+        for (address, info) in classic.payout_addresses {
+            by_name.entry(info.name.clone())
+                .or_insert_with(|| PoolDefinition { name: info.name, slug: None, addresses: vec![], tags: vec![] })
+                .addresses
+                .push(address);
+        }
+
+        let mut definitions: Vec<PoolDefinition> = by_name.into_values().collect();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+        definitions
+    }
+
+    fn from_definitions(definitions: Vec<PoolDefinition>) -> Result<Self> {
+        let mut identifier = Self::empty();
+
+        for definition in definitions {
+            let pool_index = identifier.pools.len();
+
+            for tag in definition.tags.iter() {
+                let regex = Regex::new(tag)
+                    .map_err(|e| anyhow!("Bad coinbase tag {:?} for pool {}: {}", tag, definition.name, e))?;
+                identifier.tags.push((regex, pool_index));
+            }
+
+            for address in definition.addresses {
+                identifier.addresses.insert(address, pool_index);
+            }
+
+            let slug = definition.slug.unwrap_or_else(|| slugify(&definition.name));
+            identifier.pools.push(Pool { name: definition.name, slug });
+        }
+
+        Ok(identifier)
+    }
+
+    pub fn pools_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// Сначала адрес выплаты (он надёжнее), потом теги в scriptSig coinbase.
+    pub fn identify(&self, coinbase_script: &[u8], reward_address: Option<&str>) -> PoolMatch {
+        if let Some(pool_index) = reward_address.and_then(|address| self.addresses.get(address)) {
+            return self.pool_match(*pool_index, PoolMatchMethod::PayoutAddress);
+        }
+
+        let coinbase_text = String::from_utf8_lossy(coinbase_script);
+        if let Some((_, pool_index)) = self.tags.iter().find(|(regex, _)| regex.is_match(&coinbase_text)) {
+            return self.pool_match(*pool_index, PoolMatchMethod::CoinbaseTag);
+        }
+
+        PoolMatch::unknown()
+    }
+
+    fn pool_match(&self, pool_index: usize, method: PoolMatchMethod) -> PoolMatch {
+        let pool = &self.pools[pool_index];
+
+        PoolMatch {
+            name: pool.name.clone(),
+            slug: pool.slug.clone(),
+            method,
+        }
+    }
+}
+
+/// "Foundry USA" -> "foundry-usa"
+fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+
+    for ch in name.chars() {
+        if ch.is_ascii_alphanumeric() {
+            slug.push(ch.to_ascii_lowercase());
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    slug.trim_matches('-').to_string()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn identifier(pools_file: Value) -> PoolIdentifier {
+        PoolIdentifier::from_pools_file(serde_json::from_value(pools_file).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn classic_tags_are_plain_substrings() {
+        let identifier = identifier(json!({
+            "coinbase_tags": {
+                "/F2Pool.": { "name": "F2Pool" },
+                "[ViaBTC]": { "name": "ViaBTC" }
+            }
+        }));
+
+        let f2pool = identifier.identify(b"\x03\x00\x35\x0c/F2Pool./", None);
+        assert_eq!(f2pool, PoolMatch { name: "F2Pool".to_string(), slug: "f2pool".to_string(), method: PoolMatchMethod::CoinbaseTag });
+        // Точка и скобки экранированы: это не «любой символ» и не класс символов.
+        assert!(identifier.identify(b"/F2PoolX/", None).is_unknown());
+        assert_eq!(identifier.identify(b"mined by [ViaBTC]", None).slug, "viabtc");
+        assert!(identifier.identify(b"mined by V", None).is_unknown());
+    }
+
+    #[test]
+    fn v2_regexes_are_an_alias_for_tags() {
+        let identifier = identifier(json!([
+            { "name": "Foundry USA", "slug": "foundryusa", "addresses": [], "regexes": ["Foundry USA Pool"] },
+            { "name": "AntPool", "tags": ["/AntPool/", "Mined by AntPool"] }
+        ]));
+
+        assert_eq!(identifier.pools_count(), 2);
+        assert_eq!(identifier.identify(b"/Foundry USA Pool #dropgold/", None).slug, "foundryusa");
+        assert_eq!(identifier.identify(b"Mined by AntPool bj", None).slug, "antpool");
+    }
+
+    #[test]
+    fn payout_address_takes_precedence_over_tag() {
+        let identifier = identifier(json!([
+            { "name": "Foundry USA", "addresses": ["bc1qfoundry"], "tags": ["Foundry"] },
+            { "name": "AntPool", "addresses": ["1antpool"], "tags": ["AntPool"] }
+        ]));
+
+        let by_address = identifier.identify(b"/AntPool/", Some("bc1qfoundry"));
+        assert_eq!(by_address.slug, "foundry-usa");
+        assert_eq!(by_address.method, PoolMatchMethod::PayoutAddress);
+
+        let by_tag = identifier.identify(b"/AntPool/", Some("bc1qsomeoneelse"));
+        assert_eq!(by_tag.slug, "antpool");
+        assert_eq!(by_tag.method, PoolMatchMethod::CoinbaseTag);
+
+        assert_eq!(identifier.identify(b"/solo/", None), PoolMatch::unknown());
+    }
+
+    #[test]
+    fn slugify_collapses_separators() {
+        assert_eq!(slugify("Foundry USA"), "foundry-usa");
+        assert_eq!(slugify("  Binance  Pool!! "), "binance-pool");
+        assert_eq!(slugify("SBI Crypto/1THash"), "sbi-crypto-1thash");
+        assert_eq!(slugify("F2Pool"), "f2pool");
+    }
+}
\ No newline at end of file
@@ -36,6 +36,10 @@ impl ParsedScriptSig {
         }
 
         // 0: block_height (LE)
+        if raw_pushes[0].len() > 4 {
+            error!("Error parse scriptsig: first push is longer than a block height");
+            return None;
+        }
         let block_height = {
             let raw = &raw_pushes[0];
             let mut padded = [0u8; 4];
@@ -81,4 +85,30 @@ impl ParsedScriptSig {
             raw_pushes,
         })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(script_hex: &str) -> Option<ParsedScriptSig> {
+        ParsedScriptSig::from(&ScriptBuf::from_bytes(hex::decode(script_hex).unwrap()))
+    }
+
+    #[test]
+    fn parses_miner_tag_after_height_and_timestamp() {
+        // Высота 800000, timestamp и тег пула.
+        let parsed = parse("0300350c046f2ec164082f466f756e647279").unwrap();
+
+        assert_eq!(parsed.block_height, 800_000);
+        assert_eq!(parsed.guessed_miner, "/Foundry");
+    }
+
+    #[test]
+    fn early_coinbase_without_miner_tag_is_not_parsed() {
+        // Coinbase генезиса и ранних блоков: nBits и extra nonce, тега нет.
+        assert!(parse("04ffff001d0104").is_none());
+        // Первый push длиннее высоты не должен ронять разбор.
+        assert!(parse("08000000000000000001020302aabb").is_none());
+    }
 }
\ No newline at end of file